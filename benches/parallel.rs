@@ -0,0 +1,66 @@
+/*
+    Created by Zoltan Kovari, 2024.
+
+    Licensed under the Apache License, Version 2.0
+    http://www.apache.org/licenses/LICENSE-2.0
+    (see LICENSE file)
+*/
+
+//Comparing sequential and parallel processing of a large generated file.
+//Run with: cargo bench --features parallel
+
+use std::io::{BufWriter, Write};
+use std::process::Command;
+use std::time::Instant;
+
+use chrono::{DateTime, SecondsFormat};
+
+const LINES: i64 = 2_000_000;
+
+fn run(path: &std::path::Path, extra: &[&str]) -> (f64, Vec<u8>) {
+    let start = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_csv-detect-missing"))
+        .args(["-i", "2", "-f", "rfc-3339", "--gt", "1m"])
+        .args(extra)
+        .arg(path)
+        .output()
+        .expect("could not run binary");
+    assert!(output.status.success(), "{:?}", output);
+    (start.elapsed().as_secs_f64(), output.stdout)
+}
+
+fn main() {
+    let path = std::env::temp_dir().join("csv-detect-missing-bench.csv");
+    let mut file = BufWriter::new(std::fs::File::create(&path).unwrap());
+    for i in 0..LINES {
+        //Every 1000th reading is late, to have some output as well
+        let t = 1_700_000_000 + i * 60 + i64::from(i % 1000 == 0) * 30;
+        let t = DateTime::from_timestamp(t, 0).unwrap();
+        writeln!(
+            file,
+            "{},{},{}",
+            i,
+            t.to_rfc3339_opts(SecondsFormat::Secs, true),
+            i % 97
+        )
+        .unwrap();
+    }
+    file.flush().unwrap();
+    drop(file);
+
+    let jobs = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let (sequential, expected) = run(&path, &[]);
+    println!("sequential: {:.3} s", sequential);
+    for jobs in [2, jobs] {
+        let (parallel, output) = run(&path, &["--jobs", &jobs.to_string()]);
+        assert!(output == expected, "output differs with {} jobs", jobs);
+        println!(
+            "{} jobs:     {:.3} s ({:.2}x)",
+            jobs,
+            parallel,
+            sequential / parallel
+        );
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,70 @@
+/*
+    Created by Zoltan Kovari, 2024.
+
+    Licensed under the Apache License, Version 2.0
+    http://www.apache.org/licenses/LICENSE-2.0
+    (see LICENSE file)
+*/
+
+//Comparing reading on the same thread and on a separate one, from a file and from STDIN.
+//Run with: cargo bench --bench read_ahead
+
+use std::io::{BufWriter, Write};
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+use chrono::{DateTime, SecondsFormat};
+
+const LINES: i64 = 2_000_000;
+
+fn run(path: &std::path::Path, stdin: bool, extra: &[&str]) -> (f64, Vec<u8>) {
+    let start = Instant::now();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_csv-detect-missing"));
+    command
+        .args(["-i", "2", "-f", "rfc-3339", "--gt", "1m"])
+        .args(extra);
+    match stdin {
+        true => command
+            .arg("-")
+            .stdin(Stdio::from(std::fs::File::open(path).unwrap())),
+        false => command.arg(path),
+    };
+    let output = command.output().expect("could not run binary");
+    assert!(output.status.success(), "{:?}", output);
+    (start.elapsed().as_secs_f64(), output.stdout)
+}
+
+fn main() {
+    let path = std::env::temp_dir().join("csv-detect-missing-bench-read-ahead.csv");
+    let mut file = BufWriter::new(std::fs::File::create(&path).unwrap());
+    for i in 0..LINES {
+        //Every 1000th reading is late, to have some output as well
+        let t = 1_700_000_000 + i * 60 + i64::from(i % 1000 == 0) * 30;
+        let t = DateTime::from_timestamp(t, 0).unwrap();
+        writeln!(
+            file,
+            "{},{},{}",
+            i,
+            t.to_rfc3339_opts(SecondsFormat::Secs, true),
+            i % 97
+        )
+        .unwrap();
+    }
+    file.flush().unwrap();
+    drop(file);
+
+    for (name, stdin) in [("file", false), ("stdin", true)] {
+        let (same, expected) = run(&path, stdin, &[]);
+        let (threaded, output) = run(&path, stdin, &["--threads-for-parse"]);
+        assert!(output == expected, "output differs reading {} ahead", name);
+        println!(
+            "{:5}: {:.3} s, read ahead: {:.3} s ({:.2}x)",
+            name,
+            same,
+            threaded,
+            same / threaded
+        );
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
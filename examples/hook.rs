@@ -0,0 +1,39 @@
+/*
+    Created by Zoltan Kovari, 2024.
+
+    Licensed under the Apache License, Version 2.0
+    http://www.apache.org/licenses/LICENSE-2.0
+    (see LICENSE file)
+*/
+
+//Using the detection as a library, collecting gaps into a queue instead of printing them.
+//Run with: cargo run --example hook
+
+use std::collections::VecDeque;
+
+use csv_detect_missing::*;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = Arguments::default();
+    args.index = 2;
+    args.difference = Difference::Number(4);
+    args.comment = "N/A".to_string();
+    let mut input = include_str!("../tests/synthetic/winter_olympics.csv").as_bytes();
+
+    let mut queue = VecDeque::new();
+    let tally = detect_gaps(args, &mut input, |gap: &Gap| -> Result<(), String> {
+        if queue.len() == 16 {
+            return Err("queue is full".to_string());
+        }
+        queue.push_back((gap.prev_n(), gap.n(), gap.difference().to_string()));
+        Ok(())
+    })?;
+
+    assert_eq!(queue, [(4, Some(7), "12".to_string())]);
+    println!("{:?}", tally);
+    while let Some((prev_n, n, difference)) = queue.pop_front() {
+        println!("lines {}-{}: {}", prev_n, n.unwrap(), difference);
+    }
+
+    Ok(())
+}
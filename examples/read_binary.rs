@@ -0,0 +1,41 @@
+/*
+    Created by Zoltan Kovari, 2024.
+
+    Licensed under the Apache License, Version 2.0
+    http://www.apache.org/licenses/LICENSE-2.0
+    (see LICENSE file)
+*/
+
+//Minimal reader for the output of --binary-output, printing one gap per line.
+//Usage: csv-detect-missing --binary-output ... | cargo run --example read_binary
+
+use std::io::Read;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut input = std::io::stdin().lock();
+
+    let mut header = [0u8; 5];
+    input.read_exact(&mut header)?;
+    if &header != csv_detect_missing::BINARY_HEADER {
+        return Err("unsupported input, or layout version".into());
+    }
+
+    let mut record = [0u8; 25];
+    loop {
+        match input.read_exact(&mut record) {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            r => r?,
+        }
+        let prev_line = i64::from_le_bytes(record[0..8].try_into()?);
+        let line = i64::from_le_bytes(record[8..16].try_into()?);
+        let diff = i64::from_le_bytes(record[17..25].try_into()?);
+        match record[16] {
+            0 => println!("lines {}-{}: {}", prev_line, line, diff),
+            1 => println!("lines {}-{}: {} ns", prev_line, line, diff),
+            2 => println!("lines {}-{}: incomparable", prev_line, line),
+            tag => return Err(format!("unknown tag: {}", tag).into()),
+        }
+    }
+
+    Ok(())
+}
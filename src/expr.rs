@@ -0,0 +1,198 @@
+/*
+    Created by Zoltan Kovari, 2024.
+
+    Licensed under the Apache License, Version 2.0
+    http://www.apache.org/licenses/LICENSE-2.0
+    (see LICENSE file)
+*/
+
+use super::{Difference, Format};
+
+#[derive(Debug)]
+enum Operand {
+    Diff,
+    Gap(Difference),
+}
+impl Operand {
+    fn eval(&self, diff: &Difference) -> Difference {
+        match self {
+            Self::Diff => *diff,
+            Self::Gap(gap) => *gap,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Or(Box<Node>, Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Compare(Operand, &'static str, Operand),
+}
+impl Node {
+    fn eval(&self, diff: &Difference) -> bool {
+        match self {
+            Self::Or(a, b) => a.eval(diff) || b.eval(diff),
+            Self::And(a, b) => a.eval(diff) && b.eval(diff),
+            Self::Not(a) => !a.eval(diff),
+            Self::Compare(a, op, b) => {
+                let (a, b) = (a.eval(diff), b.eval(diff));
+                match *op {
+                    ">" => a > b,
+                    ">=" => a >= b,
+                    "<" => a < b,
+                    "<=" => a <= b,
+                    "==" => a == b,
+                    _ => a != b,
+                }
+            }
+        }
+    }
+}
+
+//Boolean expression over the difference, e.g. "diff > 1h && diff < 1d", replacing the
+//comparison to a single gap. Gaps are written as for the format, like on the command line.
+#[derive(Debug)]
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+//Longer operators first, so that ">=" is not taken as ">" followed by "="
+const OPERATORS: [&str; 10] = ["&&", "||", ">=", "<=", "==", "!=", ">", "<", "!", "("];
+
+fn tokenize(s: &str) -> Result<Vec<&str>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let len = match OPERATORS
+            .iter()
+            .chain([&")"])
+            .find(|op| rest.starts_with(**op))
+        {
+            Some(op) => op.len(),
+            None => rest
+                .find(|c: char| c.is_whitespace() || "&|<>=!()".contains(c))
+                .unwrap_or(rest.len()),
+        };
+        if len == 0 {
+            return Err(format!("unexpected character '{}'", &rest[..1]));
+        }
+        tokens.push(&rest[..len]);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+//Recursive descent, from the loosest binding operator to the tightest:
+//  or      = and { "||" and }
+//  and     = unary { "&&" unary }
+//  unary   = "!" unary | "(" or ")" | operand comparison operand
+//  operand = "diff" | gap
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+    format: &'a Format,
+}
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<&str, String> {
+        let token = self.tokens.get(self.pos).ok_or("unexpected end")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        let mut node = self.and()?;
+        while self.peek() == Some("||") {
+            self.pos += 1;
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut node = self.unary()?;
+        while self.peek() == Some("&&") {
+            self.pos += 1;
+            node = Node::And(Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        match self.peek() {
+            Some("!") => {
+                self.pos += 1;
+                Ok(Node::Not(Box::new(self.unary()?)))
+            }
+            Some("(") => {
+                self.pos += 1;
+                let node = self.or()?;
+                match self.next()? {
+                    ")" => Ok(node),
+                    token => Err(format!("expected ')' instead of '{}'", token)),
+                }
+            }
+            _ => {
+                let a = self.operand()?;
+                let op = match self.next()? {
+                    ">" => ">",
+                    ">=" => ">=",
+                    "<" => "<",
+                    "<=" => "<=",
+                    "==" => "==",
+                    "!=" => "!=",
+                    token => return Err(format!("expected comparison instead of '{}'", token)),
+                };
+                Ok(Node::Compare(a, op, self.operand()?))
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let format = self.format;
+        match self.next()? {
+            "diff" => Ok(Operand::Diff),
+            token if OPERATORS.contains(&token) || token == ")" => {
+                Err(format!("expected operand instead of '{}'", token))
+            }
+            token => Ok(Operand::Gap(
+                format
+                    .parse_diff(token.to_string())
+                    .map_err(|e| e.to_string())?,
+            )),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(s: &str, format: &Format) -> Result<Self, String> {
+        let err = |e: String| format!("invalid expression '{}': {}", s, e);
+        let mut parser = Parser {
+            tokens: tokenize(s).map_err(err)?,
+            pos: 0,
+            format,
+        };
+        let root = parser.or().map_err(err)?;
+        if let Some(token) = parser.peek() {
+            return Err(err(format!("unexpected '{}'", token)));
+        }
+        Ok(Self {
+            source: s.to_string(),
+            root,
+        })
+    }
+
+    pub fn eval(&self, diff: &Difference) -> bool {
+        self.root.eval(diff)
+    }
+}
@@ -0,0 +1,67 @@
+/*
+    Created by Zoltan Kovari, 2024.
+
+    Licensed under the Apache License, Version 2.0
+    http://www.apache.org/licenses/LICENSE-2.0
+    (see LICENSE file)
+*/
+
+use std::borrow::Cow;
+
+use serde_json::Value;
+
+//Dotted path to a value in a JSON object, like "event.timestamp", elements of arrays selected by
+//their index from 0, like "readings.0.time". Keys containing a dot cannot be selected.
+#[derive(Debug, Clone)]
+pub struct JsonPath {
+    source: String,
+    keys: Vec<String>,
+}
+impl std::fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.source.fmt(f)
+    }
+}
+impl JsonPath {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let keys: Vec<String> = s.split('.').map(|key| key.to_string()).collect();
+        if keys.iter().any(|key| key.is_empty()) {
+            return Err(format!("invalid JSON path '{}': empty key", s));
+        }
+        Ok(Self {
+            source: s.to_string(),
+            keys,
+        })
+    }
+
+    //Value at the path in the line, None if missing or null. The whole line is checked to be
+    //valid JSON, nested at most 128 levels deep, not only up to the value. Strings are
+    //unescaped, numbers keep all their digits as written, while objects and arrays cannot be
+    //values.
+    pub fn select<'a>(&self, line: &'a str) -> Result<Option<Cow<'a, str>>, String> {
+        let root: Value =
+            serde_json::from_str(line).map_err(|e| format!("is not valid JSON: {}", e))?;
+        let mut value = &root;
+        for key in self.keys.iter() {
+            let next = match value {
+                Value::Object(map) => map.get(key),
+                Value::Array(items) => key.parse().ok().and_then(|i: usize| items.get(i)),
+                _ => None,
+            };
+            match next {
+                Some(next) => value = next,
+                None => return Ok(None),
+            }
+        }
+        match value {
+            Value::Null => Ok(None),
+            Value::String(s) => Ok(Some(s.clone().into())),
+            Value::Number(n) => Ok(Some(n.to_string().into())),
+            Value::Bool(b) => Ok(Some(b.to_string().into())),
+            Value::Object(_) | Value::Array(_) => Err(format!(
+                "is invalid: value at JSON path '{}' is an object or array",
+                self
+            )),
+        }
+    }
+}
@@ -6,18 +6,55 @@
     (see LICENSE file)
 */
 
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
+use std::rc::Rc;
 use std::str::FromStr;
+use std::time::Instant;
 
-use chrono::{DateTime, FixedOffset, SecondsFormat, TimeDelta};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, SecondsFormat, TimeDelta, Utc, Weekday};
+
+mod expr;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "parallel")]
+mod parallel;
+#[cfg(feature = "webhook")]
+mod webhook;
+
+pub use expr::Expr;
+#[cfg(feature = "json")]
+pub use json::JsonPath;
+#[cfg(feature = "webhook")]
+pub use webhook::Webhook;
 
 #[derive(Debug, PartialEq)]
+pub enum DetectError {
+    InvalidGap(String),
+    IncompatibleGap(String),
+}
+impl std::fmt::Display for DetectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidGap(s) => s.fmt(f),
+            Self::IncompatibleGap(s) => s.fmt(f),
+        }
+    }
+}
+impl Error for DetectError {}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum Difference {
     Number(i64),
     Duration(TimeDelta),
+    //Between values of incompatible formats, see Incomparable
+    Incomparable,
 }
 impl PartialOrd<Self> for Difference {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
@@ -28,28 +65,246 @@ impl PartialOrd<Self> for Difference {
         }
     }
 }
+impl Difference {
+    //Ordering by absolute value, descending, only within the same variant
+    fn cmp_magnitude_desc(&self, other: &Self) -> std::cmp::Ordering {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => b.unsigned_abs().cmp(&a.unsigned_abs()),
+            (Self::Duration(a), Self::Duration(b)) => b.abs().cmp(&a.abs()),
+            //Incomparable ones last
+            (Self::Incomparable, Self::Incomparable) => std::cmp::Ordering::Equal,
+            (Self::Incomparable, _) => std::cmp::Ordering::Greater,
+            (_, Self::Incomparable) => std::cmp::Ordering::Less,
+            _ => std::cmp::Ordering::Equal,
+        }
+    }
+
+    fn abs(&self) -> Difference {
+        match self {
+            Self::Number(i) => Self::Number(i.saturating_abs()),
+            Self::Duration(d) => Self::Duration(d.abs()),
+            Self::Incomparable => Self::Incomparable,
+        }
+    }
+
+    //Difference of the two, or None if it does not fit, like that of integers near their limits
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        match (self, other) {
+            (Self::Number(i), Self::Number(o)) => i.checked_sub(o).map(Self::Number),
+            (Self::Duration(d), Self::Duration(o)) => d.checked_sub(&o).map(Self::Duration),
+            _ => Some(Self::Incomparable),
+        }
+    }
+
+    fn is_positive(&self) -> bool {
+        match self {
+            Self::Number(i) => *i > 0,
+            Self::Duration(d) => *d > TimeDelta::zero(),
+            Self::Incomparable => false,
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Self::Number(i) => *i == 0,
+            Self::Duration(d) => d.is_zero(),
+            Self::Incomparable => false,
+        }
+    }
+
+    //Rounded to the nearest multiple of the unit, halves away from zero for both signs
+    fn rounded(&self, unit: &Difference) -> Difference {
+        fn round(value: i128, unit: i128) -> i128 {
+            value.signum() * ((value.abs() + unit / 2) / unit * unit)
+        }
+        fn nanos(d: &TimeDelta) -> i128 {
+            i128::from(d.num_seconds()) * 1_000_000_000 + i128::from(d.subsec_nanos())
+        }
+        match (self, unit) {
+            (Self::Number(i), Self::Number(u)) => {
+                i64::try_from(round((*i).into(), (*u).into())).map_or(*self, Self::Number)
+            }
+            (Self::Duration(d), Self::Duration(u)) => {
+                let r = round(nanos(d), nanos(u));
+                i64::try_from(r / 1_000_000_000)
+                    .ok()
+                    .and_then(|secs| TimeDelta::new(secs, 0))
+                    .map(|d| d + TimeDelta::nanoseconds((r % 1_000_000_000) as i64))
+                    .map_or(*self, Self::Duration)
+            }
+            _ => *self,
+        }
+    }
+
+    //Approximate value in seconds for durations, for relative comparisons
+    fn as_f64(&self) -> f64 {
+        match self {
+            Self::Number(i) => *i as f64,
+            Self::Duration(d) => d.num_seconds() as f64 + f64::from(d.subsec_nanos()) / 1e9,
+            Self::Incomparable => f64::NAN,
+        }
+    }
+}
+//Saturating at the limits, so that it only serves comparisons beyond them, see checked_sub
+impl std::ops::Sub for Difference {
+    type Output = Difference;
+
+    fn sub(self, other: Self) -> Self::Output {
+        match (self, other) {
+            (Self::Number(i), Self::Number(o)) => Self::Number(i.saturating_sub(o)),
+            (Self::Duration(d), Self::Duration(o)) => Self::Duration(saturating_sub(d, o)),
+            _ => Self::Incomparable,
+        }
+    }
+}
+
+fn saturating_sub(d: TimeDelta, o: TimeDelta) -> TimeDelta {
+    d.checked_sub(&o).unwrap_or(match o < TimeDelta::zero() {
+        true => TimeDelta::MAX,
+        false => TimeDelta::MIN,
+    })
+}
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(i) => i.fmt(f),
+            Self::Duration(d) => fmt_duration(d, f),
+            Self::Incomparable => "NaN".fmt(f),
+        }
+    }
+}
+
+//Rendered in seconds, with fraction only when needed
+fn fmt_duration(d: &TimeDelta, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let sign = if *d < TimeDelta::zero() { "-" } else { "" };
+    let secs = d.num_seconds().unsigned_abs();
+    let nanos = d.subsec_nanos().unsigned_abs();
+    match nanos {
+        0 => write!(f, "{}{}s", sign, secs),
+        _ => {
+            let frac = format!("{:09}", nanos);
+            write!(f, "{}{}.{}s", sign, secs, frac.trim_end_matches('0'))
+        }
+    }
+}
+
+//Parsing gap syntax, a signed integer followed by timebase, possibly
+//compound like "1h30m". Seconds may have a fraction, like "0.5s", so that
+//durations written to output can be read back. Timebases may also be given
+//by some common longer names, like "1hr30min".
+fn parse_duration(s: &str) -> Result<TimeDelta, String> {
+    let (negative, mut rest) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    if rest.is_empty() {
+        return Err("empty".to_string());
+    }
+
+    let mut total = TimeDelta::zero();
+    while !rest.is_empty() {
+        let len = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (frac, nanos) = match rest[len..].strip_prefix('.') {
+            Some(tail) => {
+                let digits = tail
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(tail.len());
+                if digits == 0 || digits > 9 {
+                    return Err("invalid fraction of seconds".to_string());
+                }
+                let nanos = i64::from_str(&format!("{:0<9}", &tail[..digits]))
+                    .map_err(|e| e.to_string())?;
+                (digits + 1, nanos)
+            }
+            None => (0, 0),
+        };
+        let tail = &rest[len + frac..];
+        let unit = &tail[..tail
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(tail.len())];
+        let base = match (len, unit, tail.chars().next()) {
+            (0, ..) | (_, _, None) => return Err("invalid value or timebase".to_string()),
+            (_, "", Some(ch)) => return Err(format!("unexpected character '{}'", ch)),
+            (_, "s" | "sec" | "secs", _) => 's',
+            (_, "m" | "min" | "mins", _) => 'm',
+            (_, "h" | "hr" | "hrs", _) => 'h',
+            (_, "d" | "day" | "days", _) => 'd',
+            (_, "w" | "wk" | "wks", _) => 'w',
+            (_, unit, _) => return Err(format!("unexpected timebase '{}'", unit)),
+        };
+        if frac > 0 && base != 's' {
+            return Err("invalid fraction of seconds".to_string());
+        }
+        let value = i64::from_str(&rest[..len]).map_err(|e| e.to_string())?;
+        let delta = match base {
+            's' => TimeDelta::try_seconds(value)
+                .and_then(|secs| secs.checked_add(&TimeDelta::nanoseconds(nanos))),
+            'm' => TimeDelta::try_minutes(value),
+            'h' => TimeDelta::try_hours(value),
+            'd' => TimeDelta::try_days(value),
+            _ => TimeDelta::try_weeks(value),
+        };
+        total = delta
+            .and_then(|delta| total.checked_add(&delta))
+            .ok_or("value out of range")?;
+        rest = &tail[unit.len()..];
+    }
+
+    match negative {
+        true => Ok(-total),
+        false => Ok(total),
+    }
+}
 
 #[derive(Copy, Clone, Debug)]
 enum Value {
     Number(i64),
     Timestamp(DateTime<FixedOffset>),
+    Duration(TimeDelta),
 }
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Number(i) => i.fmt(f),
             Self::Timestamp(t) => t.to_rfc3339_opts(SecondsFormat::AutoSi, true).fmt(f),
+            Self::Duration(d) => fmt_duration(d, f),
+        }
+    }
+}
+impl Value {
+    //Difference of the two, or None if it does not fit. Timestamps always fit, as even those
+    //at the limits of chrono (years -262143 and 262142) are much less than TimeDelta::MAX apart.
+    fn checked_sub(self, other: Self) -> Option<Difference> {
+        match (self, other) {
+            (Self::Number(i), Self::Number(o)) => i.checked_sub(o).map(Difference::Number),
+            (Self::Timestamp(t), Self::Timestamp(o)) => Some(Difference::Duration(t - o)),
+            (Self::Duration(d), Self::Duration(o)) => d.checked_sub(&o).map(Difference::Duration),
+            _ => Some(Difference::Incomparable),
         }
     }
+
+    //Difference to be reported for the line, failing it if out of range
+    fn difference(self, other: Self, n: u64) -> Result<Difference, String> {
+        self.checked_sub(other).ok_or_else(|| {
+            format!(
+                "line {} difference of {} and {} is out of range",
+                n, self, other
+            )
+        })
+    }
 }
+//Saturating at the limits, so that it only serves comparisons beyond them, see checked_sub
 impl std::ops::Sub for Value {
     type Output = Difference;
 
     fn sub(self, other: Self) -> Self::Output {
         match (self, other) {
-            (Self::Number(i), Self::Number(o)) => Difference::Number(i - o),
+            (Self::Number(i), Self::Number(o)) => Difference::Number(i.saturating_sub(o)),
             (Self::Timestamp(t), Self::Timestamp(o)) => Difference::Duration(t - o),
-            _ => panic!("cannot use subtract on Values of different variants"),
+            (Self::Duration(d), Self::Duration(o)) => Difference::Duration(saturating_sub(d, o)),
+            _ => Difference::Incomparable,
         }
     }
 }
@@ -60,7 +315,13 @@ pub enum Format {
     Int,
     Unix,
     UnixMs,
+    UnixFrac,
+    HexUnixMs,
     RFC3339,
+    NamedMonth,
+    IsoWeek,
+    AutoTime,
+    Duration,
 }
 impl TryFrom<String> for Format {
     type Error = String;
@@ -71,12 +332,76 @@ impl TryFrom<String> for Format {
             "int" => Ok(Self::Int),
             "unix" => Ok(Self::Unix),
             "unix_ms" => Ok(Self::UnixMs),
+            "unix_frac" => Ok(Self::UnixFrac),
+            "hex_unix_ms" => Ok(Self::HexUnixMs),
             "rfc-3339" => Ok(Self::RFC3339),
+            "named-month" => Ok(Self::NamedMonth),
+            "isoweek" => Ok(Self::IsoWeek),
+            "auto-time" => Ok(Self::AutoTime),
+            "duration" => Ok(Self::Duration),
             _ => Err(format!("invalid format string: '{}'", s)),
         }
     }
 }
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UInt => "uint",
+            Self::Int => "int",
+            Self::Unix => "unix",
+            Self::UnixMs => "unix_ms",
+            Self::UnixFrac => "unix_frac",
+            Self::HexUnixMs => "hex_unix_ms",
+            Self::RFC3339 => "rfc-3339",
+            Self::NamedMonth => "named-month",
+            Self::IsoWeek => "isoweek",
+            Self::AutoTime => "auto-time",
+            Self::Duration => "duration",
+        }
+        .fmt(f)
+    }
+}
 impl Format {
+    fn is_timestamp(&self) -> bool {
+        matches!(
+            self,
+            Self::Unix
+                | Self::UnixMs
+                | Self::UnixFrac
+                | Self::HexUnixMs
+                | Self::RFC3339
+                | Self::NamedMonth
+                | Self::IsoWeek
+                | Self::AutoTime
+        )
+    }
+
+    //Whether every field parsed by the other format would already be parsed by this one
+    fn shadows(&self, other: &Format) -> bool {
+        match self {
+            Self::Int => matches!(other, Self::UInt | Self::Int),
+            Self::Unix | Self::UnixMs => matches!(other, Self::Unix | Self::UnixMs),
+            Self::UnixFrac => matches!(other, Self::Unix | Self::UnixMs | Self::UnixFrac),
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+
+    //Whether values parsed by the two formats can be subtracted from each other
+    fn is_compatible(&self, other: &Format) -> bool {
+        match self {
+            Self::UInt | Self::Int => matches!(other, Self::UInt | Self::Int),
+            Self::Unix
+            | Self::UnixMs
+            | Self::UnixFrac
+            | Self::HexUnixMs
+            | Self::RFC3339
+            | Self::NamedMonth
+            | Self::IsoWeek
+            | Self::AutoTime => other.is_timestamp(),
+            Self::Duration => matches!(other, Self::Duration),
+        }
+    }
+
     fn parse_value(&self, s: String) -> Result<Value, String> {
         fn format_err(e: impl Error) -> String {
             format!("could not be parsed: {}", e)
@@ -103,49 +428,203 @@ impl Format {
                     .ok_or(timestamp_err)?
                     .into(),
             )),
+            Self::UnixFrac => {
+                let (secs, frac) = s.split_once('.').unwrap_or((s, ""));
+                if frac.len() > 9 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err("could not be parsed: invalid fraction of seconds".to_string());
+                }
+                let secs = i64::from_str(secs).map_err(format_err)?;
+                let nanos = match frac.is_empty() {
+                    true => 0,
+                    false => u32::from_str(&format!("{:0<9}", frac)).map_err(format_err)?,
+                };
+                //Fraction counts away from zero, like the sign of the integer part
+                let (secs, nanos) = match (secs < 0 || s.starts_with('-'), nanos) {
+                    (true, 1..) => (
+                        secs.checked_sub(1).ok_or(timestamp_err)?,
+                        1_000_000_000 - nanos,
+                    ),
+                    _ => (secs, nanos),
+                };
+                Ok(Value::Timestamp(
+                    DateTime::from_timestamp(secs, nanos)
+                        .ok_or(timestamp_err)?
+                        .into(),
+                ))
+            }
+            Self::HexUnixMs => {
+                let digits = s
+                    .strip_prefix("0x")
+                    .or_else(|| s.strip_prefix("0X"))
+                    .unwrap_or(s);
+                if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return Err("could not be parsed: invalid hexadecimal number".to_string());
+                }
+                let u = u64::from_str_radix(digits, 16).map_err(format_err)?;
+                let ms = i64::try_from(u)
+                    .map_err(|_| "could not be parsed: number too large (>2^63-1)".to_string())?;
+                Ok(Value::Timestamp(
+                    DateTime::from_timestamp_millis(ms)
+                        .ok_or(timestamp_err)?
+                        .into(),
+                ))
+            }
             Self::RFC3339 => {
                 //Not clear if underscore is valid in RFC3339, but it cannot hurt to allow here
-                let s = s.replace('_', "T");
+                //Comma as decimal sign for the fraction of seconds is valid in ISO 8601 though
+                let s = s.replace('_', "T").replacen(',', ".", 1);
                 Ok(Value::Timestamp(
                     DateTime::parse_from_rfc3339(&s).map_err(format_err)?,
                 ))
             }
+            Self::NamedMonth => {
+                //Two-digit years would silently be taken as the first century
+                match s.split_whitespace().nth(2) {
+                    Some(year) if year.trim_start_matches(['-', '+']).len() >= 4 => (),
+                    _ => return Err("could not be parsed: four-digit year required".to_string()),
+                }
+                //Chrono accepts English month names only, both full and abbreviated for %B
+                match DateTime::parse_from_str(s, "%d %B %Y %H:%M:%S %z") {
+                    Ok(t) => Ok(Value::Timestamp(t)),
+                    Err(_) => Ok(Value::Timestamp(
+                        chrono::NaiveDateTime::parse_from_str(s, "%d %B %Y %H:%M:%S")
+                            .map_err(format_err)?
+                            .and_utc()
+                            .into(),
+                    )),
+                }
+            }
+            Self::IsoWeek => {
+                //Like "2024-W03-1", weeks start on Monday (day 1), and week 1 of a year is the
+                //one with its first Thursday, so some years have 53 weeks
+                let invalid = "could not be parsed: expected ISO week date like 2024-W03-1";
+                let (year, rest) = s.split_once("-W").ok_or(invalid)?;
+                let (week, day) = rest.split_once('-').ok_or(invalid)?;
+                let year = i32::from_str(year).map_err(format_err)?;
+                let week = u32::from_str(week).map_err(format_err)?;
+                let day = u32::from_str(day).map_err(format_err)?;
+                if !(1..=53).contains(&week) {
+                    return Err(format!(
+                        "could not be parsed: week {} out of range 1-53",
+                        week
+                    ));
+                }
+                let weekday = match day {
+                    1..=7 => Weekday::try_from(day as u8 - 1).expect("day is in range"),
+                    _ => return Err(format!("could not be parsed: day {} out of range 1-7", day)),
+                };
+                let date = NaiveDate::from_isoywd_opt(year, week, weekday).ok_or_else(|| {
+                    format!("could not be parsed: year {} has no week {}", year, week)
+                })?;
+                Ok(Value::Timestamp(
+                    date.and_time(NaiveTime::MIN).and_utc().into(),
+                ))
+            }
+            Self::AutoTime => auto_time(s).map(|(value, _)| value),
+            Self::Duration => Ok(Value::Duration(
+                parse_duration(s).map_err(|e| format!("could not be parsed: {}", e))?,
+            )),
         }
     }
 
-    pub fn parse_diff(&self, mut s: String) -> Result<Difference, String> {
+    pub fn parse_diff(&self, mut s: String) -> Result<Difference, DetectError> {
         match self {
             Self::UInt | Self::Int => {
+                if s.ends_with(|c: char| c.is_alphabetic()) {
+                    return Err(DetectError::IncompatibleGap(format!(
+                        "invalid numeric gap '{}': unit suffixes not valid for integer formats",
+                        s
+                    )));
+                }
                 Ok(Difference::Number(i64::from_str(&s).map_err(|e| {
-                    format!("invalid numeric gap '{}': {}", s, e)
+                    DetectError::InvalidGap(format!("invalid numeric gap '{}': {}", s, e))
                 })?))
             }
-            Self::RFC3339 | Self::Unix | Self::UnixMs => {
+            Self::RFC3339
+            | Self::NamedMonth
+            | Self::IsoWeek
+            | Self::AutoTime
+            | Self::Unix
+            | Self::UnixMs
+            | Self::UnixFrac
+            | Self::HexUnixMs
+            | Self::Duration => {
                 //Converting default "1" (which is otherwise a valid UInt etc.) to "1h"
                 //Note: invalid "1" given explicitly will also be accepted this way
                 if s == "1" {
                     s = "1h".to_string();
                 }
 
-                let err_base = format!("invalid rfc-3339 gap '{}'", s.as_str());
-                let base: char = s.pop().ok_or(format!("{}: empty", &err_base))?;
-                if s.is_empty() {
-                    return Err(format!("{}: invalid value or timebase", &err_base));
-                }
-                let value = i64::from_str(&s).map_err(|e| format!("{}: {}", &err_base, e))?;
-                match base {
-                    's' => Ok(Difference::Duration(TimeDelta::seconds(value))),
-                    'm' => Ok(Difference::Duration(TimeDelta::minutes(value))),
-                    'h' => Ok(Difference::Duration(TimeDelta::hours(value))),
-                    'd' => Ok(Difference::Duration(TimeDelta::days(value))),
-                    'w' => Ok(Difference::Duration(TimeDelta::weeks(value))),
-                    ch => Err(format!("{}: unexpected character '{}'", &err_base, ch)),
+                let err_base = format!("invalid time gap '{}'", s.as_str());
+                if i64::from_str(&s).is_ok() {
+                    return Err(DetectError::IncompatibleGap(format!(
+                        "{}: unit suffix required for time based formats",
+                        &err_base
+                    )));
                 }
+                Ok(Difference::Duration(parse_duration(&s).map_err(|e| {
+                    DetectError::InvalidGap(format!("{}: {}", &err_base, e))
+                })?))
             }
         }
     }
 }
 
+//Layouts without offset tried by auto-time, taken as UTC like for named-month
+const AUTO_TIME_LAYOUTS: [&str; 3] = [
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y/%m/%d %H:%M:%S%.f",
+];
+const AUTO_DATE_LAYOUTS: [&str; 2] = ["%Y-%m-%d", "%Y/%m/%d"];
+//Epoch numbers below this magnitude are seconds (up to the year 5138), above it milliseconds
+const AUTO_EPOCH_MS_FROM: i64 = 100_000_000_000;
+
+//Trying the layouts of auto-time in order of priority: RFC 3339, then date and time without
+//offset, date only, and finally epoch numbers. Numbers of 11 or 12 digits are also returned
+//with their other reading, as both give dates far from the present, so either could be meant.
+fn auto_time(s: &str) -> Result<(Value, Option<String>), String> {
+    if let Ok(value) = Format::RFC3339.parse_value(s.to_string()) {
+        return Ok((value, None));
+    }
+    let utc = |t: chrono::NaiveDateTime| Value::Timestamp(t.and_utc().into());
+    if let Some(t) = AUTO_TIME_LAYOUTS
+        .iter()
+        .find_map(|layout| chrono::NaiveDateTime::parse_from_str(s, layout).ok())
+    {
+        return Ok((utc(t), None));
+    }
+    if let Some(date) = AUTO_DATE_LAYOUTS
+        .iter()
+        .find_map(|layout| NaiveDate::parse_from_str(s, layout).ok())
+    {
+        return Ok((utc(date.and_time(NaiveTime::MIN)), None));
+    }
+    if s.contains('.') {
+        return Format::UnixFrac
+            .parse_value(s.to_string())
+            .map(|value| (value, None))
+            .map_err(|_| "could not be parsed: no timestamp layout matches".to_string());
+    }
+    let i = i64::from_str(s)
+        .map_err(|_| "could not be parsed: no timestamp layout matches".to_string())?;
+    let (format, other) = match i.unsigned_abs() < AUTO_EPOCH_MS_FROM as u64 {
+        true => (Format::Unix, Format::UnixMs),
+        false => (Format::UnixMs, Format::Unix),
+    };
+    let value = format.parse_value(s.to_string())?;
+    let ambiguity = match i.unsigned_abs() / (AUTO_EPOCH_MS_FROM as u64 / 10) {
+        1..=99 => other.parse_value(s.to_string()).ok().map(|o| {
+            format!(
+                "taken as {} ({}) rather than {} ({})",
+                format, value, other, o
+            )
+        }),
+        _ => None,
+    };
+    Ok((value, ambiguity))
+}
+
 #[derive(Debug)]
 pub enum Comparison {
     GreaterThan,
@@ -153,6 +632,17 @@ pub enum Comparison {
     LessThan,
     LessOrEqual,
 }
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::GreaterThan => "greater than",
+            Self::GreaterOrEqual => "greater than or equal to",
+            Self::LessThan => "less than",
+            Self::LessOrEqual => "less than or equal to",
+        }
+        .fmt(f)
+    }
+}
 impl Comparison {
     fn compare(&self, a: &Difference, b: &Difference) -> bool {
         match self {
@@ -164,159 +654,3732 @@ impl Comparison {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Mode {
     Diff(String),
     Filter,
+    Csv,
+    AllDiffs(String),
+    Runs(String),
+    Binary,
+    Json,
+    Missing,
+    Echo(String),
+}
+
+//Handling of differences between values parsed by incompatible (fallback) formats: refused
+//as an error, reported as crossing the gap, or skipped
+#[derive(Debug, PartialEq)]
+pub enum Incomparable {
+    Refuse,
+    Flag,
+    Skip,
+}
+
+//Compressing output with the library of each format, if enabled by its feature
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+#[derive(Copy, Clone, Debug)]
+pub enum Compress {
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+impl Compress {
+    fn encoder(&self, inner: Box<dyn Write>) -> std::io::Result<Encoder> {
+        Ok(match *self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                inner,
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Encoder::Zstd(zstd::Encoder::new(inner, 0)?),
+        })
+    }
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+enum Encoder {
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<Box<dyn Write>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, Box<dyn Write>>),
+}
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+impl Encoder {
+    //Writing the end of the compressed stream, which is not done when just dropped
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(encoder) => encoder.finish()?.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(ref mut encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(ref mut encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(ref mut encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(ref mut encoder) => encoder.flush(),
+        }
+    }
+}
+
+//Output of a sink, compressed ones always block buffered, as the encoder holds back lines anyway
+enum Output {
+    Plain(Box<dyn Write>),
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    Compressed(BufWriter<Encoder>),
+}
+impl Output {
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(mut out) => out.flush(),
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            Self::Compressed(out) => out.into_inner().map_err(|e| e.into_error())?.finish(),
+        }
+    }
+}
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match *self {
+            Self::Plain(ref mut out) => out.write(buf),
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            Self::Compressed(ref mut out) => out.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match *self {
+            Self::Plain(ref mut out) => out.flush(),
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            Self::Compressed(ref mut out) => out.flush(),
+        }
+    }
+}
+
+//Chosen by the extension of the file if it has a known one, or by the option otherwise
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn compression(
+    path: &std::path::Path,
+    option: Option<Compress>,
+) -> Result<Option<Compress>, String> {
+    #[cfg(not(all(feature = "gzip", feature = "zstd")))]
+    let needs = |feature: &str| {
+        Err(format!(
+            "{}: compressed output needs the `{}` feature",
+            path.display(),
+            feature
+        ))
+    };
+    match path.extension().and_then(|e| e.to_str()) {
+        #[cfg(feature = "gzip")]
+        Some("gz") => Ok(Some(Compress::Gzip)),
+        #[cfg(not(feature = "gzip"))]
+        Some("gz") => needs("gzip"),
+        #[cfg(feature = "zstd")]
+        Some("zst") => Ok(Some(Compress::Zstd)),
+        #[cfg(not(feature = "zstd"))]
+        Some("zst") => needs("zstd"),
+        _ => Ok(option),
+    }
+}
+
+#[derive(Debug)]
+pub enum Buffering {
+    Auto,
+    Line,
+    Block,
 }
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct Arguments {
     pub delimiter: String,
+    pub delim_any: Option<String>,
+    pub auto_delim: bool,
+    pub squeeze: bool,
     pub index: u16,
+    pub minus_index: Option<u16>,
+    pub from_columns: Vec<u16>,
+    pub join_with: String,
+    pub exact_fields: Option<u16>,
+    pub field_width: Option<usize>,
     pub format: Format,
+    pub fallback: Vec<Format>,
+    pub incomparable: Incomparable,
     pub comparison: Comparison,
     pub difference: Difference,
+    pub warn: Option<Difference>,
+    pub severity_bands: Vec<(Difference, String)>,
+    pub expr: Option<Expr>,
+    pub thresholds: Option<PathBuf>,
+    pub expect_step: Option<i64>,
+    pub expect_start: Option<i64>,
+    pub stale: Option<Difference>,
+    pub max_future: Option<Difference>,
+    pub coalesce: Option<Difference>,
+    pub round_to: Option<Difference>,
+    pub coverage: Option<Difference>,
+    pub rate: Option<Difference>,
+    pub compare_to: Option<PathBuf>,
+    pub reference: Option<PathBuf>,
     pub comment: String,
-    pub allow_empty: bool,
+    pub reset_on: Option<String>,
+    pub allow_empty_lines: bool,
+    pub allow_empty_fields: bool,
+    pub field_default: Option<String>,
+    pub valid_column: Option<u16>,
+    pub valid_value: String,
+    pub json_strings: bool,
+    pub scale: Option<i64>,
+    pub offset: Option<i64>,
+    pub wrap_at: Option<i64>,
+    pub wrap_degrees: Option<i64>,
+    pub group_by: Option<u16>,
+    pub max_line_bytes: Option<usize>,
+    pub mark_skipped: bool,
+    pub second_diff: bool,
+    pub baseline_deviation: Option<f64>,
+    pub jitter: Option<f64>,
+    pub ratio: Option<f64>,
+    pub ema: Option<f64>,
+    pub ema_tolerance: f64,
+    pub median_window: Option<usize>,
+    pub median_tolerance: f64,
+    pub format_drift: bool,
+    pub every: Option<u64>,
+    pub dedupe_key: bool,
     pub verbose: bool,
+    pub explain: Option<u64>,
     pub mode: Mode,
+    pub outputs: Vec<(PathBuf, Mode)>,
+    pub lint: bool,
+    pub by_day: bool,
+    pub peek: Option<u64>,
+    pub number_gaps: bool,
+    pub compact: Option<String>,
+    pub with_lines: bool,
+    pub with_difference: bool,
+    pub margin: bool,
+    pub position_percent: bool,
+    pub json_meta: bool,
+    pub redact: bool,
+    pub tally: bool,
+    pub dump_diffs: Option<PathBuf>,
+    pub skip_log: Option<PathBuf>,
+    pub prometheus: Option<PathBuf>,
+    pub sort_by_gap: bool,
+    pub rank: bool,
+    pub annotate: bool,
+    pub max_buffer_bytes: u64,
+    pub buffering: Buffering,
+    pub wait_for_file: Option<u64>,
+    pub seek: Option<u64>,
+    pub state: Option<PathBuf>,
+    pub threads_for_parse: bool,
+    pub bar: bool,
     pub path: PathBuf,
+    #[cfg(feature = "encoding")]
+    pub encoding: Option<&'static encoding_rs::Encoding>,
+    #[cfg(feature = "regex")]
+    pub extract: Option<regex::Regex>,
+    #[cfg(feature = "json")]
+    pub json_field: Option<JsonPath>,
+    #[cfg(feature = "parallel")]
+    pub jobs: Option<usize>,
+    #[cfg(all(unix, feature = "socket"))]
+    pub socket: Option<PathBuf>,
+    #[cfg(all(unix, feature = "socket"))]
+    pub socket_listen: bool,
+    #[cfg(all(unix, feature = "syslog"))]
+    pub syslog: Option<u8>,
+    #[cfg(feature = "webhook")]
+    pub webhook: Option<Webhook>,
+    #[cfg(feature = "webhook")]
+    pub webhook_batch: usize,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub compress: Option<Compress>,
 }
 
-pub fn csv_detect_missing(mut args: Arguments) -> Result<(), Box<dyn Error>> {
-    if args.verbose {
-        writeln!(std::io::stdout(), "{:#?}", args)?
-    };
-
-    match args.delimiter.as_str() {
-        "\\t" => {
-            args.delimiter = char::from(9).to_string();
-            if args.verbose {
-                writeln!(std::io::stdout(), "Using Tabulator as input delimiter.")?;
-            }
+//Same as the defaults of the command line: unsigned integers in the first field separated by
+//commas, reported if greater than 1 apart, read from STDIN
+impl Default for Arguments {
+    fn default() -> Self {
+        Self {
+            delimiter: ",".to_string(),
+            delim_any: None,
+            auto_delim: false,
+            squeeze: false,
+            index: 1,
+            minus_index: None,
+            from_columns: Vec::new(),
+            join_with: " ".to_string(),
+            exact_fields: None,
+            field_width: None,
+            format: Format::UInt,
+            fallback: Vec::new(),
+            incomparable: Incomparable::Refuse,
+            comparison: Comparison::GreaterThan,
+            difference: Difference::Number(1),
+            warn: None,
+            severity_bands: Vec::new(),
+            expr: None,
+            thresholds: None,
+            expect_step: None,
+            expect_start: None,
+            stale: None,
+            max_future: None,
+            coalesce: None,
+            round_to: None,
+            coverage: None,
+            rate: None,
+            compare_to: None,
+            reference: None,
+            comment: "#".to_string(),
+            reset_on: None,
+            allow_empty_lines: false,
+            allow_empty_fields: false,
+            field_default: None,
+            valid_column: None,
+            valid_value: String::new(),
+            json_strings: false,
+            scale: None,
+            offset: None,
+            wrap_at: None,
+            wrap_degrees: None,
+            group_by: None,
+            mark_skipped: false,
+            second_diff: false,
+            baseline_deviation: None,
+            jitter: None,
+            ratio: None,
+            ema: None,
+            ema_tolerance: 50.0,
+            median_window: None,
+            median_tolerance: 50.0,
+            format_drift: false,
+            every: None,
+            dedupe_key: false,
+            max_line_bytes: None,
+            verbose: false,
+            explain: None,
+            mode: Mode::Diff(",".to_string()),
+            outputs: Vec::new(),
+            lint: false,
+            by_day: false,
+            peek: None,
+            number_gaps: false,
+            compact: None,
+            with_lines: false,
+            with_difference: false,
+            margin: false,
+            position_percent: false,
+            json_meta: false,
+            redact: false,
+            tally: false,
+            dump_diffs: None,
+            skip_log: None,
+            prometheus: None,
+            sort_by_gap: false,
+            rank: false,
+            annotate: false,
+            max_buffer_bytes: 1 << 30,
+            buffering: Buffering::Auto,
+            wait_for_file: None,
+            seek: None,
+            state: None,
+            threads_for_parse: false,
+            bar: false,
+            path: "-".into(),
+            #[cfg(feature = "encoding")]
+            encoding: None,
+            #[cfg(feature = "regex")]
+            extract: None,
+            #[cfg(feature = "json")]
+            json_field: None,
+            #[cfg(feature = "parallel")]
+            jobs: None,
+            #[cfg(all(unix, feature = "socket"))]
+            socket: None,
+            #[cfg(all(unix, feature = "socket"))]
+            socket_listen: false,
+            #[cfg(all(unix, feature = "syslog"))]
+            syslog: None,
+            #[cfg(feature = "webhook")]
+            webhook: None,
+            #[cfg(feature = "webhook")]
+            webhook_batch: 1,
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            compress: None,
         }
-        "" => {
-            if args.index != 1 {
-                return Err("supplied index and delimiter are incompatible".into());
-            } else if args.verbose {
-                writeln!(
-                    std::io::stdout(),
-                    "No delimiter, using whole line as target field.",
-                )?;
-            }
+    }
+}
+
+//Binary output starts with magic and layout version, see README
+pub const BINARY_HEADER: &[u8; 5] = b"CDMB\x01";
+
+//Quoting field for CSV output according to RFC 4180, only when needed
+fn quote_csv(field: &str) -> Cow<'_, str> {
+    match field.contains([',', '"', '\r', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")).into(),
+        false => field.into(),
+    }
+}
+
+//Quoting field the same way for any delimiter, only when needed
+fn quote_delimited<'a>(field: &'a str, delim: &str) -> Cow<'a, str> {
+    match (!delim.is_empty() && field.contains(delim)) || field.contains('"') {
+        true => format!("\"{}\"", field.replace('"', "\"\"")).into(),
+        false => field.into(),
+    }
+}
+
+//Two-tier alerting, CRIT crossing the main gap and WARN the warning threshold only
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    Warn,
+    Crit,
+}
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ok => "OK",
+            Self::Warn => "WARN",
+            Self::Crit => "CRIT",
         }
-        _ => (),
+        .fmt(f)
     }
-    if let Mode::Diff(ref odelim) = args.mode {
-        match odelim.as_str() {
-            "\\t" => {
-                args.mode = Mode::Diff(char::from(9).to_string());
-                if args.verbose {
-                    writeln!(std::io::stdout(), "Using Tabulator as output delimiter.")?;
-                }
-            }
-            "" => {
-                args.mode = Mode::Diff(args.delimiter.clone());
-                if args.verbose {
-                    writeln!(
-                        std::io::stdout(),
-                        "No output delimiter, using same as input.",
-                    )?;
-                }
-            }
-            _ => (),
+}
+
+//Quoting string for JSON output, escaping as needed
+fn quote_json(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
         }
     }
+    quoted.push('"');
+    quoted
+}
 
-    let mut reader: Box<dyn BufRead> = if args.path == PathBuf::from("-") {
-        Box::new(BufReader::new(std::io::stdin().lock()))
-    } else {
-        Box::new(BufReader::new(File::open(args.path)?))
+//Unescaping field if quoted as a JSON string, leaving it as is otherwise
+fn unquote_json(s: &str) -> Result<Cow<'_, str>, String> {
+    let inner = match s.trim().strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(inner) if inner.contains('\\') => inner,
+        Some(inner) => return Ok(inner.into()),
+        None => return Ok(s.into()),
     };
+    fn invalid(e: &str) -> String {
+        format!("is not a valid JSON string: {}", e)
+    }
+    fn hex(chars: &mut std::str::Chars) -> Result<u32, String> {
+        let digits: String = chars.take(4).collect();
+        match digits.len() {
+            4 => u32::from_str_radix(&digits, 16).map_err(|e| invalid(&e.to_string())),
+            _ => Err(invalid("incomplete unicode escape")),
+        }
+    }
 
-    let mut buf = String::new();
-    let mut n: u64 = 0;
-    struct Previous {
-        line: String,
-        value: Value,
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        let c = match (c, c == '\\') {
+            ('"', _) => return Err(invalid("unescaped quote")),
+            (c, false) => c,
+            (_, true) => match chars.next() {
+                Some('"') => '"',
+                Some('\\') => '\\',
+                Some('/') => '/',
+                Some('b') => '\u{8}',
+                Some('f') => '\u{c}',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('u') => {
+                    let code = match hex(&mut chars)? {
+                        //High surrogate must be followed by an escaped low one
+                        high @ 0xd800..=0xdbff => match (chars.next(), chars.next()) {
+                            (Some('\\'), Some('u')) => match hex(&mut chars)? {
+                                low @ 0xdc00..=0xdfff => {
+                                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                                }
+                                _ => return Err(invalid("unpaired surrogate")),
+                            },
+                            _ => return Err(invalid("unpaired surrogate")),
+                        },
+                        code => code,
+                    };
+                    char::from_u32(code).ok_or(invalid("unpaired surrogate"))?
+                }
+                Some(c) => return Err(invalid(&format!("unknown escape '\\{}'", c))),
+                None => return Err(invalid("unfinished escape")),
+            },
+        };
+        unquoted.push(c);
     }
-    let mut prev: Option<Previous> = None;
-    let mut first = true;
+    Ok(unquoted.into())
+}
 
-    while reader.read_line(&mut buf)? > 0 {
-        n += 1;
-        let line = buf.trim();
+//A reported difference between two values, the second of which may not come from a line
+pub struct Gap<'a> {
+    prev_n: u64,
+    n: Option<u64>,
+    prev_line: &'a str,
+    line: Option<&'a str>,
+    prev_value: Value,
+    value: Value,
+    difference: Difference,
+    severity: Option<Severity>,
+    threshold: Option<Difference>,
+    group: Option<&'a str>,
+}
+impl Gap<'_> {
+    pub fn prev_n(&self) -> u64 {
+        self.prev_n
+    }
 
-        'processing: {
-            if !args.comment.is_empty() && line.starts_with(&args.comment) {
-                break 'processing;
-            }
-            if line.is_empty() {
-                match args.allow_empty {
-                    true => break 'processing,
-                    false => return Err(format!("line {} is empty", n).into()),
-                }
-            };
+    //None when compared to the current time (stale check)
+    pub fn n(&self) -> Option<u64> {
+        self.n
+    }
 
-            let field = match args.delimiter.is_empty() {
-                true => line,
-                false => match line
-                    .split(&args.delimiter)
-                    .nth((args.index.checked_sub(1).unwrap()).into())
-                {
-                    Some(s) if !s.is_empty() => s,
-                    Some(_) if args.allow_empty => break 'processing,
-                    Some(_) => {
-                        return Err(format!(
-                            "line {} is invalid: empty field at index {}",
-                            n, args.index,
-                        )
-                        .into())
-                    }
-                    None if args.allow_empty => break 'processing,
-                    None => {
-                        return Err(format!(
-                            "line {} is invalid: no field could be found at index {}",
-                            n, args.index,
-                        )
-                        .into())
-                    }
-                },
-            };
+    pub fn prev_line(&self) -> &str {
+        self.prev_line
+    }
 
-            let value = args
-                .format
-                .parse_value(field.to_string())
-                .map_err(|e| format!("line {} field '{}' {}", n, field, e))?;
+    pub fn line(&self) -> Option<&str> {
+        self.line
+    }
 
-            if let Some(prev) = prev {
-                let diff = value - prev.value;
+    pub fn prev_value(&self) -> impl std::fmt::Display + '_ {
+        &self.prev_value
+    }
 
-                let condition = args.comparison.compare(&diff, &args.difference);
-                if condition {
-                    match args.mode {
-                        Mode::Diff(ref delim) => {
-                            writeln!(std::io::stdout(), "{}{}{}", prev.value, delim, value)?
-                        }
-                        Mode::Filter => {
-                            match first {
-                                true => first = false,
-                                false => writeln!(std::io::stdout())?,
-                            }
-                            writeln!(std::io::stdout(), "{}\n{}", prev.line, line)?;
-                        }
-                    }
-                }
-            }
+    pub fn value(&self) -> impl std::fmt::Display + '_ {
+        &self.value
+    }
 
-            prev = Some(Previous {
-                line: line.to_string(),
-                value,
-            });
-        }
+    pub fn difference(&self) -> &Difference {
+        &self.difference
+    }
 
-        buf.clear();
+    //None when no warning threshold is given
+    pub fn severity(&self) -> Option<Severity> {
+        self.severity
+    }
+
+    //None when not compared to a gap (e.g. runs, or sequence, baseline, jitter or ratio mode)
+    pub fn threshold(&self) -> Option<&Difference> {
+        self.threshold.as_ref()
+    }
+
+    //None when not grouped
+    pub fn group(&self) -> Option<&str> {
+        self.group
+    }
+}
+
+//Number of differences over, equal to, and under the gap (or stale threshold)
+#[derive(Debug, Default)]
+pub struct Tally {
+    pub over: u64,
+    pub equal: u64,
+    pub under: u64,
+    pub coverage: Option<Coverage>,
+    pub rate: Option<Rate>,
+    pub jitter: Option<Jitter>,
+    //Line of the last value, for saving the state
+    last: Option<String>,
+}
+
+//Span from the lowest to the highest value, with the number of values expected in it by the
+//cadence (rounding the intervals, to tolerate jitter), and the number actually present
+#[derive(Debug)]
+pub struct Coverage {
+    pub span: Difference,
+    pub expected: u64,
+    pub actual: u64,
+}
+impl Coverage {
+    pub fn percent(&self) -> f64 {
+        self.actual as f64 / self.expected as f64 * 100.0
+    }
+}
+
+//Number of values per unit of time over the span from the lowest to the highest value, counting
+//the intervals between them, so not the first value
+#[derive(Debug)]
+pub struct Rate {
+    pub span: Difference,
+    pub values: u64,
+    pub unit: Difference,
+}
+impl Rate {
+    //None for a span of zero (e.g. a single value), as there is no rate to tell then
+    pub fn per_unit(&self) -> Option<f64> {
+        match self.span.as_f64() {
+            span if span > 0.0 => {
+                Some(self.values.saturating_sub(1) as f64 / span * self.unit.as_f64())
+            }
+            _ => None,
+        }
+    }
+}
+
+//Mean and standard deviation of the differences (in seconds for durations), accumulated by
+//Welford's online algorithm, which is numerically stable in a single pass
+#[derive(Debug, Default)]
+pub struct Jitter {
+    pub count: u64,
+    pub mean: f64,
+    m2: f64,
+}
+impl Jitter {
+    fn add(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    //Sample standard deviation, zero until there are two differences
+    pub fn std_dev(&self) -> f64 {
+        match self.count {
+            0 | 1 => 0.0,
+            n => (self.m2 / (n - 1) as f64).sqrt(),
+        }
+    }
+}
+
+//Last differences in order of arrival, and the same kept sorted, for their running median.
+//Both insertion and removal are linear in the size of the window, which is small in practice.
+#[derive(Debug, Default)]
+struct MedianWindow {
+    order: std::collections::VecDeque<f64>,
+    sorted: Vec<f64>,
+}
+impl MedianWindow {
+    fn push(&mut self, x: f64, size: usize) {
+        if self.order.len() == size {
+            let oldest = self.order.pop_front().expect("window is not empty");
+            let i = self.sorted.partition_point(|v| *v < oldest);
+            self.sorted.remove(i);
+        }
+        self.order.push_back(x);
+        let i = self.sorted.partition_point(|v| *v < x);
+        self.sorted.insert(i, x);
+    }
+
+    //Mean of the two middle values for an even count
+    fn median(&self) -> Option<f64> {
+        let n = self.sorted.len();
+        match n {
+            0 => None,
+            _ if n % 2 == 1 => Some(self.sorted[n / 2]),
+            _ => Some((self.sorted[n / 2 - 1] + self.sorted[n / 2]) / 2.0),
+        }
+    }
+}
+
+//Replacing data with a reference to its line number, if redacted
+fn redacted(redact: bool, n: Option<u64>, data: &dyn std::fmt::Display) -> String {
+    match (redact, n) {
+        (true, Some(n)) => format!("#{}", n),
+        _ => data.to_string(),
+    }
+}
+
+//Writing one gap to output, according to mode, with its position in percent through the input
+fn write_gap(
+    out: &mut dyn Write,
+    args: &Arguments,
+    mode: &Mode,
+    gap: &Gap,
+    written: u64,
+    position: Option<f64>,
+) -> std::io::Result<()> {
+    let prev_value = redacted(args.redact, Some(gap.prev_n), &gap.prev_value);
+    let value = redacted(args.redact, gap.n, &gap.value);
+    //Rounded for display only, as comparison was done in full precision
+    let difference = args
+        .round_to
+        .map_or(gap.difference, |unit| gap.difference.rounded(&unit));
+    let severity = |delim: &str| match gap.severity {
+        Some(severity) => format!("{}{}", delim, severity),
+        None => String::new(),
+    };
+    let band = band(args, &gap.difference);
+    let band_column = |delim: &str| match (args.severity_bands.is_empty(), band) {
+        (true, _) => String::new(),
+        (false, Some(label)) => format!("{}{}", delim, label),
+        (false, None) => format!("{}-", delim),
+    };
+    let position = |delim: &str, unit: &str| match position {
+        Some(percent) => format!("{}{:.1}{}", delim, percent, unit),
+        None => String::new(),
+    };
+    //How far the difference is from the gap: their ratio for numbers, their difference otherwise,
+    //also for a gap of zero, which has no ratio
+    let margin = |delim: &str| match (args.margin, gap.threshold) {
+        (true, Some(threshold)) => match (gap.difference, threshold) {
+            (Difference::Number(d), Difference::Number(t)) if t != 0 => {
+                format!("{}{:.2}x", delim, d as f64 / t as f64)
+            }
+            (d, t) => format!("{}{}", delim, d - t),
+        },
+        _ => String::new(),
+    };
+    let with_difference = |delim: &str| match args.with_difference {
+        true => format!("{}{}", delim, difference),
+        false => String::new(),
+    };
+    let lines = |delim: &str| match args.with_lines {
+        true => format!(
+            "{}{}{}{}",
+            delim,
+            quote_delimited(
+                &redacted(args.redact, Some(gap.prev_n), &gap.prev_line),
+                delim
+            ),
+            delim,
+            quote_delimited(
+                &redacted(args.redact, gap.n, &gap.line.unwrap_or_default()),
+                delim
+            ),
+        ),
+        false => String::new(),
+    };
+    //Source of the value: the key of its group if grouped, or its column otherwise
+    let source = match gap.group {
+        Some(group) => Some(group.to_string()),
+        None if args.annotate => Some(column_label(args)),
+        None => None,
+    };
+    let group = |delim: &str| match source {
+        Some(ref source) if delim == "," => format!("{}{}", quote_csv(source), delim),
+        Some(ref source) => format!("{}{}", source, delim),
+        None => String::new(),
+    };
+    //Gaps are written largest first when ranked, so the count so far is the rank
+    let rank = |delim: &str| match args.rank {
+        true => format!("{}{}", written + 1, delim),
+        false => String::new(),
+    };
+
+    match *mode {
+        Mode::Diff(ref delim) if args.redact => writeln!(
+            out,
+            "{}{}{}{}{}{}{}{}{}{}{}{}",
+            rank(delim),
+            group(delim),
+            prev_value,
+            delim,
+            value,
+            delim,
+            difference,
+            severity(delim),
+            band_column(delim),
+            margin(delim),
+            position(delim, "%"),
+            lines(delim)
+        ),
+        Mode::Diff(ref delim) => writeln!(
+            out,
+            "{}{}{}{}{}{}{}{}{}{}{}",
+            rank(delim),
+            group(delim),
+            prev_value,
+            delim,
+            value,
+            with_difference(delim),
+            severity(delim),
+            band_column(delim),
+            margin(delim),
+            position(delim, "%"),
+            lines(delim)
+        ),
+        Mode::Filter if args.compact.is_some() => {
+            let separator = args.compact.as_deref().unwrap_or_default();
+            match gap.line {
+                Some(line) => writeln!(out, "{}{}{}", gap.prev_line, separator, line),
+                None => writeln!(out, "{}", gap.prev_line),
+            }
+        }
+        Mode::Filter => {
+            match (args.number_gaps, gap.n, gap.line) {
+                (true, Some(n), Some(_)) => {
+                    writeln!(out, "# gap {} (lines {}-{})", written + 1, gap.prev_n, n)?
+                }
+                (true, ..) => writeln!(out, "# gap {} (line {})", written + 1, gap.prev_n)?,
+                (false, ..) if written > 0 => writeln!(out)?,
+                (false, ..) => (),
+            }
+            writeln!(out, "{}", gap.prev_line)?;
+            match gap.line {
+                Some(line) => writeln!(out, "{}", line),
+                None => Ok(()),
+            }
+        }
+        Mode::Csv => writeln!(
+            out,
+            "{}{}{},{},{},{},{}{}{}{}",
+            rank(","),
+            group(","),
+            quote_csv(&redacted(args.redact, Some(gap.prev_n), &gap.prev_line)),
+            quote_csv(&redacted(args.redact, gap.n, &gap.line.unwrap_or_default())),
+            quote_csv(&prev_value),
+            quote_csv(&value),
+            difference,
+            severity(","),
+            band_column(","),
+            position(",", ""),
+        ),
+        Mode::AllDiffs(ref delim) => writeln!(
+            out,
+            "{}{}{}{}{}{}{}{}{}",
+            group(delim),
+            prev_value,
+            delim,
+            value,
+            delim,
+            difference,
+            severity(delim),
+            band_column(delim),
+            position(delim, "%")
+        ),
+        //Runs are reported from their first value to their last, see detect
+        Mode::Runs(ref delim) => writeln!(
+            out,
+            "{}{}{}{}{}{}{}{}",
+            group(delim),
+            prev_value,
+            delim,
+            value,
+            delim,
+            gap.n.unwrap_or(gap.prev_n) - gap.prev_n + 1,
+            delim,
+            difference,
+        ),
+        Mode::Binary => {
+            let (tag, diff): (u8, i64) = match gap.difference {
+                Difference::Number(i) => (0, i),
+                Difference::Duration(d) => (
+                    1,
+                    d.num_nanoseconds().unwrap_or(match d < TimeDelta::zero() {
+                        true => i64::MIN,
+                        false => i64::MAX,
+                    }),
+                ),
+                Difference::Incomparable => (2, 0),
+            };
+            out.write_all(&(gap.prev_n as i64).to_le_bytes())?;
+            out.write_all(&(gap.n.unwrap_or(0) as i64).to_le_bytes())?;
+            out.write_all(&[tag])?;
+            out.write_all(&diff.to_le_bytes())
+        }
+        //Values are echoed without comparison, see csv_detect_missing
+        Mode::Echo(_) => Ok(()),
+        Mode::Missing => match (gap.prev_value, gap.value, args.expect_step) {
+            (Value::Number(prev), Value::Number(value), Some(step)) => {
+                let mut missing = prev.checked_add(step);
+                while let Some(m) =
+                    missing.filter(|m| (step > 0 && *m < value) || (step < 0 && *m > value))
+                {
+                    writeln!(out, "{}{}", group(","), m)?;
+                    missing = m.checked_add(step);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        },
+        Mode::Json => writeln!(
+            out,
+            "{{{}{}\"prev_line_number\":{},\"line_number\":{},\"prev_line\":{},\"line\":{},\
+             \"prev_value\":{},\"value\":{},\"difference\":{}{}{}{}}}",
+            match (args.annotate, source) {
+                (true, Some(ref source)) => format!("\"source\":{},", quote_json(source)),
+                _ => String::new(),
+            },
+            match gap.group {
+                Some(group) => format!("\"group\":{},", quote_json(group)),
+                None => String::new(),
+            },
+            gap.prev_n,
+            gap.n.map_or("null".to_string(), |n| n.to_string()),
+            quote_json(&redacted(args.redact, Some(gap.prev_n), &gap.prev_line)),
+            match gap.line {
+                Some(line) => quote_json(&redacted(args.redact, gap.n, &line)),
+                None => "null".to_string(),
+            },
+            quote_json(&prev_value),
+            quote_json(&value),
+            quote_json(&difference.to_string()),
+            match gap.severity {
+                Some(severity) => format!(",\"severity\":\"{}\"", severity),
+                None => String::new(),
+            },
+            match (args.severity_bands.is_empty(), band) {
+                (true, _) => String::new(),
+                (false, Some(label)) => format!(",\"band\":{}", quote_json(label)),
+                (false, None) => ",\"band\":null".to_string(),
+            },
+            position(",\"position\":", ""),
+        ),
+    }
+}
+
+//Column of the value as given on the command line, e.g. "3", "-1" or "1+2" for joined columns
+fn column_label(args: &Arguments) -> String {
+    match (args.minus_index, args.from_columns.is_empty()) {
+        (Some(index), _) => format!("-{}", index),
+        (None, true) => args.index.to_string(),
+        (None, false) => args
+            .from_columns
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<String>>()
+            .join("+"),
+    }
+}
+
+//Header of output, if any for the mode
+fn write_header(out: &mut dyn Write, args: &Arguments, mode: &Mode) -> std::io::Result<()> {
+    match *mode {
+        Mode::Csv => writeln!(
+            out,
+            "{}{}prev_line,line,prev_value,value,difference{}{}{}",
+            match args.rank {
+                true => "rank,",
+                false => "",
+            },
+            match (args.annotate, args.group_by) {
+                (true, _) => "source,",
+                (false, Some(_)) => "group,",
+                (false, None) => "",
+            },
+            match args.warn {
+                Some(_) => ",severity",
+                None => "",
+            },
+            match args.severity_bands.is_empty() {
+                true => "",
+                false => ",band",
+            },
+            match args.position_percent {
+                true => ",position",
+                false => "",
+            }
+        ),
+        Mode::Binary => out.write_all(BINARY_HEADER),
+        Mode::Json if args.json_meta => writeln!(
+            out,
+            "{{\"meta\":{{\"version\":\"{}\",\"path\":{},\"format\":\"{}\",\
+             \"comparison\":\"{}\",\"gap\":\"{}\",\"started\":\"{}\"}}}}",
+            clap::crate_version!(),
+            quote_json(&args.path.to_string_lossy()),
+            std::iter::once(&args.format)
+                .chain(&args.fallback)
+                .map(|f| f.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+            match (&args.stale, &args.max_future, &args.expr, &args.comparison) {
+                (Some(_), _, _, _) => "stale",
+                (None, Some(_), _, _) => "max-future",
+                (None, None, Some(_), _) => "expr",
+                (None, None, None, Comparison::GreaterThan) => "gt",
+                (None, None, None, Comparison::GreaterOrEqual) => "ge",
+                (None, None, None, Comparison::LessThan) => "lt",
+                (None, None, None, Comparison::LessOrEqual) => "le",
+            },
+            match (&args.stale, &args.max_future, &args.expr) {
+                (Some(gap), _, _) | (None, Some(gap), _) => gap.to_string(),
+                //Operands have to be valid gaps, so there is nothing to escape
+                (None, None, Some(expr)) => expr.to_string(),
+                (None, None, None) => args.difference.to_string(),
+            },
+            Utc::now().to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        ),
+        _ => Ok(()),
+    }
+}
+
+//Transcoding to UTF-8 first if an input encoding was given
+#[cfg_attr(not(feature = "encoding"), allow(unused_variables))]
+fn decode_line<'a>(bytes: &'a [u8], args: &Arguments) -> std::io::Result<Cow<'a, str>> {
+    #[cfg(feature = "encoding")]
+    if let Some(encoding) = args.encoding {
+        return Ok(encoding.decode_without_bom_handling(bytes).0);
+    }
+    std::str::from_utf8(bytes)
+        .map(Cow::Borrowed)
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "is not valid UTF-8"))
+}
+
+//Checking length of line without line ending (LF or CRLF), if limited
+fn check_length(bytes: &[u8], args: &Arguments) -> std::io::Result<()> {
+    let line = bytes
+        .strip_suffix(b"\r\n")
+        .or_else(|| bytes.strip_suffix(b"\n"))
+        .unwrap_or(bytes);
+    match args.max_line_bytes {
+        Some(max) if line.len() > max => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("is too long, exceeding {} bytes", max),
+        )),
+        _ => Ok(()),
+    }
+}
+
+//Limiting reading to two bytes over maximum (room for a CRLF line ending), so that memory is not
+//exhausted
+fn read_limit(args: &Arguments) -> u64 {
+    args.max_line_bytes
+        .map_or(u64::MAX, |max| (max as u64).saturating_add(2))
+}
+
+//Reading a line into bytes first (reused by the caller), to be checked and decoded into buf
+fn read_line(
+    reader: &mut dyn BufRead,
+    bytes: &mut Vec<u8>,
+    buf: &mut String,
+    args: &Arguments,
+) -> std::io::Result<usize> {
+    bytes.clear();
+    let len = (&mut *reader)
+        .take(read_limit(args))
+        .read_until(b'\n', bytes)?;
+    check_length(bytes, args)?;
+    buf.push_str(&decode_line(bytes, args)?);
+    Ok(len)
+}
+
+//Leading whitespace is significant with fixed width fields, and so is a delimiter like " :: "
+//that the line starts or ends with, for it to still separate an empty field. A delimiter of only
+//whitespace, like a space or tab, is trimmed as any other whitespace.
+fn trim_line<'a>(line: &'a str, args: &Arguments) -> &'a str {
+    if args.field_width.is_some() {
+        return line.trim_end();
+    }
+    let delimiter = match args.delim_any {
+        Some(_) => None,
+        None => Some(args.delimiter.as_str()).filter(|d| !d.trim().is_empty()),
+    };
+    let line = line.trim_end_matches(['\r', '\n']);
+    let line = match delimiter.is_some_and(|d| line.starts_with(d)) {
+        true => line,
+        false => line.trim_start(),
+    };
+    match delimiter.is_some_and(|d| line.ends_with(d)) {
+        true => line,
+        false => line.trim_end(),
+    }
+}
+
+enum Parsed {
+    Value(Value),
+    //Values of the fields at minus index and index, in within-row mode
+    Pair(Value, Value),
+    Comment,
+    Reset,
+    Skipped(Skip),
+}
+
+//Why a line was skipped, written to the skip log as a reason code
+#[derive(Copy, Clone, Debug)]
+enum Skip {
+    Empty,
+    NotValid,
+    Missing,
+}
+impl std::fmt::Display for Skip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => "empty",
+            Self::NotValid => "not-valid",
+            Self::Missing => "missing",
+        }
+        .fmt(f)
+    }
+}
+
+//Collapsing runs of whitespace into a single space, for irregularly spaced fields
+fn squeezed<'a>(line: &'a str, args: &Arguments) -> Cow<'a, str> {
+    match args.squeeze {
+        true => Cow::Owned(line.split_whitespace().collect::<Vec<&str>>().join(" ")),
+        false => Cow::Borrowed(line),
+    }
+}
+
+//Parsing target field of a line
+fn parse_line(line: &str, args: &Arguments) -> Result<Parsed, String> {
+    if !args.comment.is_empty() && line.starts_with(&args.comment) {
+        return Ok(Parsed::Comment);
+    }
+    if let Some(ref sentinel) = args.reset_on {
+        if line.starts_with(sentinel) {
+            return Ok(Parsed::Reset);
+        }
+    }
+    if line.is_empty() {
+        return match args.allow_empty_lines {
+            true => Ok(Parsed::Skipped(Skip::Empty)),
+            false => Err("is empty".to_string()),
+        };
+    };
+    let squeezed = squeezed(line, args);
+    let line = squeezed.as_ref();
+    if let Some(expected) = args.exact_fields {
+        let count = split_fields(line, args).count();
+        if count != usize::from(expected) {
+            return Err(format!(
+                "is invalid: {} fields found instead of {}",
+                count, expected
+            ));
+        }
+    }
+
+    //Lines not marked valid (including those without the status field) are skipped
+    if let Some(index) = args.valid_column {
+        let status = select_field(line, index, args).ok().flatten();
+        if status != Some(args.valid_value.as_str()) {
+            return Ok(Parsed::Skipped(Skip::NotValid));
+        }
+    }
+
+    #[cfg(feature = "regex")]
+    let extracted = args
+        .extract
+        .as_ref()
+        .map(|re| re.captures(line).and_then(|c| c.get(1)).map(|m| m.as_str()));
+    #[cfg(not(feature = "regex"))]
+    let extracted: Option<Option<&str>> = None;
+
+    let field = match extracted {
+        Some(Some(s)) if !s.is_empty() => Some(s.into()),
+        Some(_) if args.allow_empty_fields || args.field_default.is_some() => None,
+        Some(_) => return Err("is invalid: no value could be extracted by pattern".to_string()),
+        None => target_field(line, args)?,
+    };
+    let value = match field_value(field.as_deref(), args)? {
+        Some(value) => value,
+        None => return Ok(Parsed::Skipped(Skip::Missing)),
+    };
+
+    match args.minus_index {
+        Some(index) => match field_value(select_field(line, index, args)?, args)? {
+            Some(start) => Ok(Parsed::Pair(start, value)),
+            None => Ok(Parsed::Skipped(Skip::Missing)),
+        },
+        None => Ok(Parsed::Value(value)),
+    }
+}
+
+//Raw text of the selected field in a line, for telling apart differently written equal values
+fn raw_field(line: &str, args: &Arguments) -> Option<String> {
+    let line = squeezed(line, args);
+    #[cfg(feature = "regex")]
+    if let Some(ref re) = args.extract {
+        return re
+            .captures(&line)
+            .and_then(|c| c.get(1))
+            .map(|m| m.as_str().to_string());
+    }
+    target_field(&line, args)
+        .ok()
+        .flatten()
+        .map(|field| field.into_owned())
+}
+
+//Parsing selected field, or the default in its place if missing, then transforming it
+fn field_value(field: Option<&str>, args: &Arguments) -> Result<Option<Value>, String> {
+    let value = match (field, &args.field_default) {
+        (Some(field), _) => parse_field(field, args)?,
+        (None, Some(default)) => parse_field(default, args)?,
+        (None, None) => return Ok(None),
+    };
+    transform(value, args).map(Some)
+}
+
+//Applying scale first, then offset (in seconds for time based formats)
+fn transform(value: Value, args: &Arguments) -> Result<Value, String> {
+    let scaled = match (value, args.scale) {
+        (Value::Number(i), Some(scale)) => i.checked_mul(scale).map(Value::Number),
+        (value, _) => Some(value),
+    };
+    let offset = args.offset.map(|o| (o, TimeDelta::try_seconds(o)));
+    match (scaled, offset) {
+        (Some(value), None) => Some(value),
+        (Some(Value::Number(i)), Some((o, _))) => i.checked_add(o).map(Value::Number),
+        (Some(Value::Timestamp(t)), Some((_, Some(d)))) => {
+            t.checked_add_signed(d).map(Value::Timestamp)
+        }
+        (Some(Value::Duration(v)), Some((_, Some(d)))) => v.checked_add(&d).map(Value::Duration),
+        _ => None,
+    }
+    .ok_or(format!(
+        "value {} is out of range after scale and offset",
+        value
+    ))
+}
+
+//Splitting line into fields by the delimiter, or by any of the delimiter characters if given
+fn split_fields<'a: 'b, 'b>(
+    line: &'a str,
+    args: &'b Arguments,
+) -> Box<dyn Iterator<Item = &'a str> + 'b> {
+    match args.delim_any {
+        Some(ref chars) => Box::new(line.split(|c| chars.contains(c))),
+        None => Box::new(line.split(args.delimiter.as_str())),
+    }
+}
+
+//Selecting the target field, or joining the fields of each column if given, like a date and a
+//time split in two. None if any of them is missing but allowed to be.
+fn target_field<'a>(line: &'a str, args: &Arguments) -> Result<Option<Cow<'a, str>>, String> {
+    #[cfg(feature = "json")]
+    if let Some(ref path) = args.json_field {
+        return match path.select(line)? {
+            Some(value) => Ok(Some(value)),
+            None if args.allow_empty_fields || args.field_default.is_some() => Ok(None),
+            None => Err(format!("is invalid: no value at JSON path '{}'", path)),
+        };
+    }
+    if args.from_columns.is_empty() {
+        return Ok(select_field(line, args.index, args)?.map(Cow::Borrowed));
+    }
+    let mut fields = Vec::new();
+    for index in args.from_columns.iter() {
+        match select_field(line, *index, args)? {
+            Some(field) => fields.push(field),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(fields.join(&args.join_with).into()))
+}
+
+//Selecting field at index by width or delimiter, None if missing but allowed to be
+fn select_field<'a>(
+    line: &'a str,
+    index: u16,
+    args: &Arguments,
+) -> Result<Option<&'a str>, String> {
+    let missing_allowed = args.allow_empty_fields || args.field_default.is_some();
+    let fields = |count: usize| match count {
+        1 => "1 field".to_string(),
+        n => format!("{} fields", n),
+    };
+    match args.field_width {
+        Some(width) => {
+            let start = usize::from(index - 1) * width;
+            let mut bounds = line.char_indices().map(|(i, _)| i).chain([line.len()]);
+            match (bounds.nth(start), bounds.nth(width - 1)) {
+                (Some(begin), end) if begin < line.len() => {
+                    match &line[begin..end.unwrap_or(line.len())] {
+                        s if !s.trim().is_empty() => Ok(Some(s)),
+                        _ if missing_allowed => Ok(None),
+                        _ => Err(format!("is invalid: empty field at index {}", index)),
+                    }
+                }
+                _ if missing_allowed => Ok(None),
+                _ => Err(format!(
+                    "is invalid: no field could be found at index {} (only {} found)",
+                    index,
+                    fields(line.chars().count().div_ceil(width)),
+                )),
+            }
+        }
+        None if args.delimiter.is_empty() => Ok(Some(line)),
+        None => match split_fields(line, args).nth((index.checked_sub(1).unwrap()).into()) {
+            Some(s) if !s.is_empty() => Ok(Some(s)),
+            Some(_) if missing_allowed => Ok(None),
+            Some(_) => Err(format!("is invalid: empty field at index {}", index)),
+            None if missing_allowed => Ok(None),
+            //Counted again only on this path, so that the common case is not slowed down
+            None => Err(format!(
+                "is invalid: no field could be found at index {} (only {} found)",
+                index,
+                fields(split_fields(line, args).count()),
+            )),
+        },
+    }
+}
+
+//Parsing field by format, trying fallback formats in order
+fn parse_field(field: &str, args: &Arguments) -> Result<Value, String> {
+    let original = field;
+    let field = match args.json_strings {
+        true => unquote_json(field).map_err(|e| format!("field '{}' {}", original, e))?,
+        false => Cow::Borrowed(field),
+    };
+    if args.verbose && matches!(args.format, Format::AutoTime) {
+        if let Ok((_, Some(ambiguity))) = auto_time(field.trim().trim_matches('"')) {
+            writeln!(
+                std::io::stderr(),
+                "Warning: field '{}' is ambiguous, {}.",
+                original,
+                ambiguity
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    match args.format.parse_value(field.to_string()) {
+        Err(e) if !args.fallback.is_empty() => args
+            .fallback
+            .iter()
+            .find_map(|f| f.parse_value(field.to_string()).ok())
+            .ok_or(format!("{} (nor by fallback formats)", e)),
+        result => result,
+    }
+    .map_err(|e| format!("field '{}' {}", original, e))
+}
+
+//Reading valid values one by one, ignoring comments, resets and skipped lines
+struct ValueReader {
+    path: PathBuf,
+    reader: BufReader<File>,
+    bytes: Vec<u8>,
+    buf: String,
+    n: u64,
+}
+impl ValueReader {
+    fn open(path: &PathBuf) -> std::io::Result<Self> {
+        Ok(Self {
+            path: path.clone(),
+            reader: BufReader::new(File::open(path)?),
+            bytes: Vec::new(),
+            buf: String::new(),
+            n: 0,
+        })
+    }
+
+    fn next(&mut self, args: &Arguments) -> Result<Option<(u64, String, Value)>, String> {
+        loop {
+            self.buf.clear();
+            let len = read_line(&mut self.reader, &mut self.bytes, &mut self.buf, args)
+                .map_err(|e| format!("{} line {} {}", self.path.display(), self.n + 1, e))?;
+            if len == 0 {
+                return Ok(None);
+            }
+            self.n += 1;
+
+            let line = trim_line(&self.buf, args);
+            match parse_line(line, args)
+                .map_err(|e| format!("{} line {} {}", self.path.display(), self.n, e))?
+            {
+                Parsed::Value(value) => return Ok(Some((self.n, line.to_string(), value))),
+                Parsed::Comment | Parsed::Reset | Parsed::Skipped(_) => (),
+                Parsed::Pair(..) => unreachable!("within-row mode cannot compare to file"),
+            }
+        }
+    }
+}
+
+type Handler<'a> = dyn FnMut(u64, &str, Parsed) -> Result<(), Box<dyn Error>> + 'a;
+
+//Reading input line by line, passing parsed values to the handler
+fn for_each_line(
+    reader: &mut dyn BufRead,
+    args: &Arguments,
+    handle: &mut Handler,
+) -> Result<(), Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    let mut buf = String::new();
+    let mut n: u64 = 0;
+
+    while read_line(reader, &mut bytes, &mut buf, args)
+        .map_err(|e| format!("line {} {}", n + 1, e))?
+        > 0
+    {
+        //Possibly still being written, left for the next run to read whole, see write_state
+        if args.state.is_some() && !buf.ends_with('\n') {
+            break;
+        }
+        n += 1;
+        let line = trim_line(&buf, args);
+
+        let parsed = parse_line(line, args).map_err(|e| format!("line {} {}", n, e))?;
+        handle(n, line, parsed)?;
+
+        buf.clear();
+    }
+
+    Ok(())
+}
+
+//First lines of the input (other than comments and empty ones), without consuming them
+fn sample_lines(args: &Arguments, reader: &mut dyn BufRead) -> std::io::Result<Vec<String>> {
+    let buf = reader.fill_buf()?;
+    //The last line may be cut off by the end of the buffer
+    let complete = match buf.iter().rposition(|b| *b == b'\n') {
+        Some(i) => &buf[..i],
+        None => buf,
+    };
+    Ok(String::from_utf8_lossy(complete)
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter(|l| args.comment.is_empty() || !l.starts_with(&args.comment))
+        .take(10)
+        .map(|l| l.to_string())
+        .collect())
+}
+
+//Guessing the delimiter from the first few lines of input, without consuming them: of the
+//candidates, the one found the same number of times on the most lines wins, or the one found
+//more times if equal. Falls back to comma if none is found, or it is still ambiguous.
+fn sniff_delimiter(args: &mut Arguments, reader: &mut dyn BufRead) -> Result<(), Box<dyn Error>> {
+    if !args.auto_delim {
+        return Ok(());
+    }
+    let lines = sample_lines(args, reader)?;
+
+    let mut scores: Vec<(usize, usize, char)> = [',', '\t', ';', '|']
+        .into_iter()
+        .filter_map(|c| {
+            let counts: Vec<usize> = lines.iter().map(|l| l.matches(c).count()).collect();
+            counts
+                .iter()
+                .filter(|count| **count > 0)
+                .map(|count| {
+                    (
+                        counts.iter().filter(|other| *other == count).count(),
+                        *count,
+                    )
+                })
+                .max()
+                .map(|(lines, count)| (lines, count, c))
+        })
+        .collect();
+    scores.sort_by_key(|(lines, count, _)| std::cmp::Reverse((*lines, *count)));
+
+    match scores.as_slice() {
+        [(lines, count, c), rest @ ..]
+            if rest
+                .first()
+                .map_or(true, |r| (r.0, r.1) != (*lines, *count)) =>
+        {
+            args.delimiter = c.to_string();
+            if args.verbose {
+                writeln!(std::io::stdout(), "Detected {:?} as input delimiter.", c)?;
+            }
+        }
+        _ => {
+            args.delimiter = ",".to_string();
+            writeln!(
+                std::io::stderr(),
+                "Warning: delimiter could not be detected, using comma.",
+            )?;
+        }
+    }
+    Ok(())
+}
+
+//Guessing the format of the selected field in the first lines, by how many values it parses,
+//preferring integers on a tie, which are then told apart by their magnitude
+pub fn probe_format(args: &mut Arguments) -> Result<Format, Box<dyn Error>> {
+    let mut reader = open_input(args, false)?;
+    sniff_delimiter(args, &mut reader)?;
+    //Not to be detected again on the same input
+    args.auto_delim = false;
+
+    let lines: Vec<String> = sample_lines(args, &mut reader)?
+        .iter()
+        .map(|line| squeezed(line, args).into_owned())
+        .collect();
+    let fields: Vec<String> = lines
+        .iter()
+        .filter_map(|line| target_field(line, args).ok().flatten())
+        .map(|field| match args.json_strings {
+            true => unquote_json(&field).map_or(field.to_string(), |f| f.into_owned()),
+            false => field.into_owned(),
+        })
+        .collect();
+    let parsed = |format: &Format| -> Vec<Value> {
+        fields
+            .iter()
+            .filter_map(|field| format.parse_value(field.clone()).ok())
+            .collect()
+    };
+
+    let candidates = [
+        Format::Int,
+        Format::UnixFrac,
+        Format::HexUnixMs,
+        Format::RFC3339,
+        Format::NamedMonth,
+        Format::IsoWeek,
+        Format::Duration,
+    ];
+    let (count, format) = candidates
+        .into_iter()
+        .map(|format| (parsed(&format).len(), format))
+        .enumerate()
+        .max_by_key(|(i, (count, _))| (*count, std::cmp::Reverse(*i)))
+        .map(|(_, best)| best)
+        .unwrap();
+    if count == 0 {
+        return Err(format!(
+            "format could not be probed, none of the first {} values could be parsed",
+            fields.len()
+        )
+        .into());
+    }
+
+    let format = match format {
+        Format::Int => {
+            let numbers: Vec<i64> = parsed(&Format::Int)
+                .into_iter()
+                .filter_map(|value| match value {
+                    Value::Number(i) => Some(i),
+                    _ => None,
+                })
+                .collect();
+            //Seconds since 1973 and milliseconds since 1973 respectively, up to a few millennia
+            let within = |min: i64, max: i64| numbers.iter().all(|i| (min..max).contains(i));
+            if within(100_000_000, 100_000_000_000) {
+                Format::Unix
+            } else if within(100_000_000_000, 100_000_000_000_000) {
+                Format::UnixMs
+            } else if numbers.iter().all(|i| *i >= 0) {
+                Format::UInt
+            } else {
+                Format::Int
+            }
+        }
+        format => format,
+    };
+    writeln!(
+        std::io::stderr(),
+        "Probed format: {} ({} of {} values parsed)",
+        format,
+        count,
+        fields.len()
+    )?;
+    Ok(format)
+}
+
+//Printing the fields of the first line of data with their indexes, to help choosing one
+pub fn list_fields(args: &mut Arguments) -> Result<(), Box<dyn Error>> {
+    let mut reader = open_input(args, false)?;
+    sniff_delimiter(args, &mut reader)?;
+    //Normally done by prepare, along with checks not needed here
+    if args.delimiter == "\\t" {
+        args.delimiter = char::from(9).to_string();
+    }
+
+    let line = match sample_lines(args, &mut reader)?.into_iter().next() {
+        Some(line) => line,
+        None => return Err("no line of data found to list fields of".into()),
+    };
+    let line = squeezed(&line, args);
+    let mut out = std::io::stdout().lock();
+    for (i, field) in split_fields(&line, args).enumerate() {
+        writeln!(out, "{}: {}", i + 1, field)?;
+    }
+    Ok(())
+}
+
+//Normalizing and validating arguments, before any input is read
+fn prepare(args: &mut Arguments) -> Result<(), Box<dyn Error>> {
+    if args.verbose {
+        writeln!(std::io::stdout(), "{:#?}", args)?
+    };
+
+    //The first of any delimiter characters is taken as the delimiter, e.g. for output
+    if let Some(ref mut chars) = args.delim_any {
+        *chars = chars.replace("\\t", &char::from(9).to_string());
+        args.delimiter = match chars.chars().next() {
+            Some(first) => first.to_string(),
+            None => return Err("set of delimiter characters is empty".into()),
+        };
+        if args.verbose {
+            writeln!(
+                std::io::stdout(),
+                "Splitting on any of {:?}, using '{}' as input delimiter otherwise.",
+                chars,
+                args.delimiter,
+            )?;
+        }
+    }
+    match args.delimiter.as_str() {
+        "\\t" => {
+            args.delimiter = char::from(9).to_string();
+            if args.verbose {
+                writeln!(std::io::stdout(), "Using Tabulator as input delimiter.")?;
+            }
+        }
+        "" => {
+            if args.index != 1 || args.minus_index.is_some() || !args.from_columns.is_empty() {
+                return Err("supplied index and delimiter are incompatible".into());
+            } else if args.verbose {
+                writeln!(
+                    std::io::stdout(),
+                    "No delimiter, using whole line as target field.",
+                )?;
+            }
+        }
+        _ => (),
+    }
+    if let Some(width) = args.field_width {
+        if args.verbose {
+            writeln!(
+                std::io::stdout(),
+                "Using fixed width fields of {} characters, ignoring delimiter.",
+                width,
+            )?;
+        }
+    }
+    if !args.from_columns.is_empty() && args.minus_index.is_some() {
+        return Err("joined columns cannot be combined with within-row mode".into());
+    }
+    #[cfg(feature = "regex")]
+    if args.extract.is_some() && !args.from_columns.is_empty() {
+        return Err("extract pattern cannot be combined with joined columns".into());
+    }
+    #[cfg(feature = "json")]
+    if args.json_field.is_some() {
+        if args.minus_index.is_some()
+            || !args.from_columns.is_empty()
+            || args.field_width.is_some()
+            || args.group_by.is_some()
+            || args.valid_column.is_some()
+            || args.exact_fields.is_some()
+        {
+            return Err("JSON path cannot be combined with selecting fields by index".into());
+        }
+        #[cfg(feature = "regex")]
+        if args.extract.is_some() {
+            return Err("JSON path cannot be combined with extract pattern".into());
+        }
+        if args.verbose {
+            writeln!(
+                std::io::stdout(),
+                "Parsing lines as JSON, ignoring delimiter and index.",
+            )?;
+        }
+    }
+    #[cfg(feature = "regex")]
+    if let Some(ref re) = args.extract {
+        if re.captures_len() != 2 {
+            return Err("extract pattern must contain exactly one capture group".into());
+        } else if args.verbose {
+            writeln!(
+                std::io::stdout(),
+                "Extracting by pattern, ignoring delimiter and index.",
+            )?;
+        }
+    }
+    let (delimiter, verbose) = (args.delimiter.clone(), args.verbose);
+    let modes = std::iter::once(&mut args.mode).chain(args.outputs.iter_mut().map(|(_, m)| m));
+    for mode in modes {
+        if let Mode::Diff(ref mut odelim)
+        | Mode::AllDiffs(ref mut odelim)
+        | Mode::Runs(ref mut odelim)
+        | Mode::Echo(ref mut odelim) = *mode
+        {
+            match odelim.as_str() {
+                "\\t" => {
+                    *odelim = char::from(9).to_string();
+                    if verbose {
+                        writeln!(std::io::stdout(), "Using Tabulator as output delimiter.")?;
+                    }
+                }
+                "" => {
+                    *odelim = delimiter.clone();
+                    if verbose {
+                        writeln!(
+                            std::io::stdout(),
+                            "No output delimiter, using same as input.",
+                        )?;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    //Fallback formats must yield values comparable to the primary, and must not be shadowed
+    let formats: Vec<&Format> = std::iter::once(&args.format)
+        .chain(&args.fallback)
+        .collect();
+    for (i, f) in formats.iter().enumerate() {
+        if !args.format.is_compatible(f) && args.incomparable == Incomparable::Refuse {
+            return Err(format!(
+                "fallback format '{}' is incompatible with primary format '{}'",
+                f, args.format
+            )
+            .into());
+        }
+        if let Some(earlier) = formats[..i].iter().find(|e| e.shadows(f)) {
+            return Err(format!(
+                "fallback format '{}' would never be tried after '{}'",
+                f, earlier
+            )
+            .into());
+        }
+    }
+
+    if let Some(ref warn) = args.warn {
+        let valid = match args.comparison {
+            Comparison::GreaterThan | Comparison::GreaterOrEqual => *warn < args.difference,
+            Comparison::LessThan | Comparison::LessOrEqual => *warn > args.difference,
+        };
+        if !valid {
+            return Err(format!(
+                "warning threshold {} must be less severe than gap {}",
+                warn, args.difference
+            )
+            .into());
+        }
+    }
+    for pair in args.severity_bands.windows(2) {
+        if pair[0].0 >= pair[1].0 {
+            return Err(format!(
+                "severity band {}={} must be above {}={}",
+                pair[1].0, pair[1].1, pair[0].0, pair[0].1
+            )
+            .into());
+        }
+    }
+    if let Some((threshold, label)) = args.severity_bands.first() {
+        if !(threshold.is_positive() || threshold.is_zero()) {
+            return Err(
+                format!("severity band {}={} must not be negative", threshold, label).into(),
+            );
+        }
+    }
+    if args.warn.is_some() && !args.severity_bands.is_empty() {
+        return Err("severity bands cannot be combined with warning threshold".into());
+    }
+    if args.scale.is_some() && !matches!(args.format, Format::UInt | Format::Int) {
+        return Err("scale requires a numeric format".into());
+    }
+    if args.group_by.is_some()
+        && std::iter::once(&args.mode)
+            .chain(args.outputs.iter().map(|(_, mode)| mode))
+            .any(|mode| matches!(mode, Mode::Binary))
+    {
+        return Err("binary output cannot be annotated with groups".into());
+    }
+    if args.wrap_at.is_some() && !matches!(args.format, Format::UInt | Format::Int) {
+        return Err("counter wraparound requires a numeric format".into());
+    }
+    if args.wrap_degrees.is_some() && !matches!(args.format, Format::UInt | Format::Int) {
+        return Err("circular wraparound requires a numeric format".into());
+    }
+    if args.wrap_degrees.is_some_and(|range| range < 2) {
+        return Err("circular range must be at least 2".into());
+    }
+    if args.wrap_at.is_some() && args.wrap_degrees.is_some() {
+        return Err("counter and circular wraparound cannot be combined".into());
+    }
+    if let Some(ref default) = args.field_default {
+        parse_field(default, args).map_err(|e| format!("default {}", e))?;
+    }
+    if args.exact_fields.is_some() && (args.delimiter.is_empty() || args.field_width.is_some()) {
+        return Err("exact field count requires fields separated by a delimiter".into());
+    }
+    if args.every.is_some() && (args.minus_index.is_some() || args.compare_to.is_some()) {
+        return Err("sampling cannot be combined with within-row mode or compare".into());
+    }
+    if args.dedupe_key && (args.minus_index.is_some() || args.reference.is_some()) {
+        return Err(
+            "dropping repeated values cannot be combined with within-row mode or \
+reference check"
+                .into(),
+        );
+    }
+    if args.minus_index.is_some()
+        && (args.stale.is_some() || args.max_future.is_some() || args.compare_to.is_some())
+    {
+        return Err(
+            "within-row mode cannot be combined with stale check, future check or compare".into(),
+        );
+    }
+    if let Some(step) = args.expect_step {
+        if !matches!(args.format, Format::UInt | Format::Int) {
+            return Err("expected sequence requires an integer format".into());
+        } else if step == 0 {
+            return Err("expected step must not be zero".into());
+        } else if args
+            .expect_start
+            .is_some_and(|start| start.checked_sub(step).is_none())
+        {
+            return Err("expected start is out of range".into());
+        }
+    }
+    if matches!(args.mode, Mode::Runs(_))
+        && (args.stale.is_some()
+            || args.max_future.is_some()
+            || args.compare_to.is_some()
+            || args.minus_index.is_some())
+    {
+        return Err(
+            "runs mode cannot be combined with stale or future check, compare or within-row mode"
+                .into(),
+        );
+    }
+    if let Some(ref window) = args.coalesce {
+        if args.stale.is_some()
+            || args.max_future.is_some()
+            || args.compare_to.is_some()
+            || args.minus_index.is_some()
+            || matches!(
+                args.mode,
+                Mode::AllDiffs(_) | Mode::Runs(_) | Mode::Missing | Mode::Echo(_)
+            )
+        {
+            return Err(
+                "coalescing gaps cannot be combined with stale or future check, compare, \
+within-row mode, or all-diffs, runs, missing or echo mode"
+                    .into(),
+            );
+        }
+        if *window < Difference::Number(0) || *window < Difference::Duration(TimeDelta::zero()) {
+            return Err(format!("coalescing window {} must not be negative", window).into());
+        }
+    }
+    if args.reference.is_some()
+        && (args.stale.is_some()
+            || args.max_future.is_some()
+            || args.compare_to.is_some()
+            || args.minus_index.is_some()
+            || args.group_by.is_some()
+            || args.thresholds.is_some()
+            || args.expect_step.is_some()
+            || args.second_diff
+            || args.coalesce.is_some()
+            || matches!(args.mode, Mode::Runs(_)))
+    {
+        return Err(
+            "reference check cannot be combined with stale or future check, compare, \
+within-row mode, grouping, thresholds, expected sequence, second differences, coalescing or \
+runs mode"
+                .into(),
+        );
+    }
+    if args.state.is_some()
+        && (args.group_by.is_some()
+            || args.stale.is_some()
+            || args.max_future.is_some()
+            || args.compare_to.is_some()
+            || args.minus_index.is_some()
+            || args.reference.is_some())
+    {
+        return Err(
+            "state cannot be kept with grouping, stale or future check, compare, \
+within-row mode or reference check"
+                .into(),
+        );
+    }
+    if args.expr.is_some()
+        && (args.warn.is_some()
+            || args.thresholds.is_some()
+            || args.stale.is_some()
+            || args.max_future.is_some()
+            || args.expect_step.is_some()
+            || args.baseline_deviation.is_some()
+            || args.jitter.is_some()
+            || args.ratio.is_some()
+            || args.ema.is_some()
+            || args.median_window.is_some()
+            || args.format_drift
+            || args.margin)
+    {
+        return Err(
+            "expression cannot be combined with warning threshold, thresholds file, \
+stale or future check, expected step, baseline, jitter, ratio, EMA, median, format drift or \
+margin"
+                .into(),
+        );
+    }
+    if args.median_window == Some(0) {
+        return Err("median window must hold at least one difference".into());
+    }
+    if args.ema.is_some_and(|alpha| !(alpha > 0.0 && alpha <= 1.0)) {
+        return Err("EMA smoothing factor must be greater than 0 and at most 1".into());
+    }
+    if args.lint
+        && (!matches!(args.mode, Mode::Diff(_))
+            || !args.outputs.is_empty()
+            || args.mark_skipped
+            || args.sort_by_gap
+            || args.rank)
+    {
+        return Err("lint cannot be combined with other output modes, outputs, \
+marking skipped lines or sorting"
+            .into());
+    }
+    if args.by_day
+        && (!matches!(args.mode, Mode::Diff(_))
+            || !args.outputs.is_empty()
+            || args.mark_skipped
+            || args.lint
+            || args.sort_by_gap
+            || args.rank)
+    {
+        return Err(
+            "counting by day cannot be combined with other output modes, outputs, \
+marking skipped lines, lint or sorting"
+                .into(),
+        );
+    }
+    if args.by_day
+        && !std::iter::once(&args.format)
+            .chain(&args.fallback)
+            .all(|f| f.is_timestamp())
+    {
+        return Err("counting by day requires a timestamp format".into());
+    }
+    if args.peek.is_some()
+        && (!matches!(args.mode, Mode::Diff(_))
+            || !args.outputs.is_empty()
+            || args.mark_skipped
+            || args.lint
+            || args.by_day
+            || args.sort_by_gap
+            || args.rank
+            || args.redact)
+    {
+        return Err(
+            "peeking cannot be combined with other output modes, outputs, \
+marking skipped lines, lint, counting by day, sorting or redaction"
+                .into(),
+        );
+    }
+    if args.peek.is_some()
+        && (args.path.as_os_str() == "-" || args.seek.is_some() || args.state.is_some())
+    {
+        return Err(
+            "peeking requires reading the whole file again, not STDIN, seeking or state".into(),
+        );
+    }
+    if args.rank && !matches!(args.mode, Mode::Diff(_) | Mode::Csv) {
+        return Err("rank requires diff or CSV output".into());
+    }
+    if args.annotate
+        && std::iter::once(&args.mode)
+            .chain(args.outputs.iter().map(|(_, mode)| mode))
+            .any(|mode| matches!(mode, Mode::Filter | Mode::Binary | Mode::Echo(_)))
+    {
+        return Err(
+            "annotation requires diff, all-diffs, runs, CSV, JSON or missing output".into(),
+        );
+    }
+    if args.ratio.is_some() && !matches!(args.format, Format::UInt | Format::Int) {
+        return Err("ratio mode requires an integer format".into());
+    }
+    if matches!(args.mode, Mode::Missing) && args.expect_step.is_none() {
+        return Err("listing missing values requires an expected step".into());
+    }
+    if args.json_meta
+        && !std::iter::once(&args.mode)
+            .chain(args.outputs.iter().map(|(_, mode)| mode))
+            .any(|mode| matches!(mode, Mode::Json))
+    {
+        return Err("run metadata requires JSON output".into());
+    }
+    if args
+        .round_to
+        .as_ref()
+        .is_some_and(|unit| !unit.is_positive())
+    {
+        return Err("rounding unit must be positive".into());
+    }
+    if let Some(ref cadence) = args.coverage {
+        if !args.format.is_timestamp() {
+            return Err("coverage requires a timestamp format".into());
+        }
+        if !cadence.is_positive() {
+            return Err(format!("coverage cadence {} must be positive", cadence).into());
+        }
+    }
+    if let Some(ref unit) = args.rate {
+        if !args.format.is_timestamp() {
+            return Err("rate requires a timestamp format".into());
+        }
+        if !unit.is_positive() {
+            return Err(format!("rate unit {} must be positive", unit).into());
+        }
+    }
+    if args.stale.is_some() && !args.format.is_timestamp() {
+        return Err("stale check requires a timestamp format".into());
+    }
+    if let Some(ref max_future) = args.max_future {
+        if !args.format.is_timestamp() {
+            return Err("future check requires a timestamp format".into());
+        }
+        if *max_future < Difference::Duration(TimeDelta::zero()) {
+            return Err(format!("future threshold {} must not be negative", max_future).into());
+        }
+    }
+
+    Ok(())
+}
+
+//Loading gaps applicable from given values on, as lines of "FROM,GAP" in ascending order
+fn load_thresholds(path: &PathBuf, args: &Arguments) -> Result<Vec<(Value, Difference)>, String> {
+    let err = |n: usize, e: &dyn std::fmt::Display| format!("{} line {} {}", path.display(), n, e);
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut thresholds: Vec<(Value, Difference)> = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (!args.comment.is_empty() && line.starts_with(&args.comment)) {
+            continue;
+        }
+        let (from, gap) = line
+            .split_once(',')
+            .ok_or(err(i + 1, &"is invalid: expected \"FROM,GAP\""))?;
+        let from = parse_field(from.trim(), args).map_err(|e| err(i + 1, &e))?;
+        let gap = args
+            .format
+            .parse_diff(gap.trim().to_string())
+            .map_err(|e| err(i + 1, &e))?;
+        if let Some((last, _)) = thresholds.last() {
+            if !(from - *last).is_positive() {
+                return Err(err(i + 1, &"is out of order, values must be ascending"));
+            }
+        }
+        thresholds.push((from, gap));
+    }
+    Ok(thresholds)
+}
+
+//Error for features keeping data in memory, when it would grow beyond the limit
+fn buffer_exceeded(args: &Arguments) -> String {
+    format!(
+        "data kept in memory would exceed {} bytes, see --max-buffer-bytes",
+        args.max_buffer_bytes
+    )
+}
+
+//Loading expected values for the reference check, one per line in ascending order
+fn load_reference(path: &PathBuf, args: &Arguments) -> Result<Vec<(u64, String, Value)>, String> {
+    let err = |n: usize, e: &dyn std::fmt::Display| format!("{} line {} {}", path.display(), n, e);
+    let len = std::fs::metadata(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?
+        .len();
+    if args.max_buffer_bytes > 0 && len > args.max_buffer_bytes {
+        return Err(buffer_exceeded(args));
+    }
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut reference: Vec<(u64, String, Value)> = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (!args.comment.is_empty() && line.starts_with(&args.comment)) {
+            continue;
+        }
+        let value = parse_field(line, args).map_err(|e| err(i + 1, &e))?;
+        if let Some((_, _, last)) = reference.last() {
+            if !(value - *last).is_positive() {
+                return Err(err(i + 1, &"is out of order, values must be ascending"));
+            }
+        }
+        reference.push((i as u64 + 1, line.to_string(), value));
+    }
+    Ok(reference)
+}
+
+//Connecting to a Unix socket for output, or waiting for a single client if listening. Line
+//buffered unless requested otherwise, so that records are pushed as soon as found.
+#[cfg(all(unix, feature = "socket"))]
+fn open_socket(
+    path: &PathBuf,
+    listen: bool,
+    buffering: &Buffering,
+) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let err = |e: std::io::Error| format!("socket {}: {}", path.display(), e);
+    let stream = match listen {
+        true => {
+            let listener = UnixListener::bind(path).map_err(err)?;
+            let accepted = listener.accept();
+            //Removed as soon as possible, not to block the next run
+            std::fs::remove_file(path).map_err(err)?;
+            accepted.map_err(err)?.0
+        }
+        false => UnixStream::connect(path).map_err(err)?,
+    };
+    Ok(match buffering {
+        Buffering::Block => Box::new(BufWriter::new(stream)),
+        Buffering::Line | Buffering::Auto => Box::new(std::io::LineWriter::new(stream)),
+    })
+}
+
+//Local syslog daemon, receiving each gap as a message in the traditional BSD format, under
+//the user facility
+#[cfg(all(unix, feature = "syslog"))]
+struct Syslog {
+    logger: syslog::Logger<syslog::LoggerBackend, syslog::Formatter3164>,
+    severity: u8,
+}
+#[cfg(all(unix, feature = "syslog"))]
+impl Syslog {
+    //Trying the usual socket paths of Linux, then macOS and the BSDs
+    fn connect(severity: u8) -> Result<Self, syslog::Error> {
+        let logger = syslog::unix(syslog::Formatter3164 {
+            facility: syslog::Facility::LOG_USER,
+            hostname: None,
+            process: "csv-detect-missing".to_string(),
+            pid: std::process::id(),
+        })?;
+        Ok(Self { logger, severity })
+    }
+
+    fn send(&mut self, message: &str) -> Result<(), syslog::Error> {
+        match self.severity {
+            0 => self.logger.emerg(message),
+            1 => self.logger.alert(message),
+            2 => self.logger.crit(message),
+            3 => self.logger.err(message),
+            4 => self.logger.warning(message),
+            5 => self.logger.notice(message),
+            6 => self.logger.info(message),
+            _ => self.logger.debug(message),
+        }
+    }
+}
+
+//Queueing a gap to be sent to the webhook, in the same JSON as in JSON output
+#[cfg(feature = "webhook")]
+fn notify(
+    batch: &mut webhook::Batch,
+    args: &Arguments,
+    gap: &Gap,
+    position: Option<f64>,
+) -> std::io::Result<()> {
+    let mut json = Vec::new();
+    write_gap(&mut json, args, &Mode::Json, gap, 0, position)?;
+    batch.push(String::from_utf8_lossy(&json).trim_end().to_string())
+}
+
+//Describing a gap in a single line for syslog or lint, with line numbers and magnitude
+fn gap_message(args: &Arguments, gap: &Gap) -> String {
+    format!(
+        "{}gap of {} between line {} and {} ({} -> {}){}",
+        gap.group
+            .map_or(String::new(), |g| format!("group {}: ", g)),
+        args.round_to
+            .map_or(gap.difference, |unit| gap.difference.rounded(&unit)),
+        gap.prev_n,
+        gap.n.map_or("now".to_string(), |n| n.to_string()),
+        redacted(args.redact, Some(gap.prev_n), &gap.prev_value),
+        redacted(args.redact, gap.n, &gap.value),
+        gap.severity.map_or(String::new(), |s| format!(" [{}]", s)),
+    )
+}
+
+//Processing lines sequentially, or parsing them in parallel if requested
+fn process(
+    reader: &mut dyn BufRead,
+    args: &Arguments,
+    handle: &mut Handler,
+) -> Result<(), Box<dyn Error>> {
+    #[cfg(feature = "parallel")]
+    if let Some(jobs) = args.jobs {
+        return parallel::for_each_line(reader, args, jobs, handle);
+    }
+    for_each_line(reader, args, handle)
+}
+
+//Recovering the true increment of a counter wrapped at its maximum. Only a negative jump
+//larger than half of the range counts as a wrap, smaller ones are taken as they are.
+//For circular values like angles, taking the shorter way around instead, into [-range/2,
+//range/2), so that 350 to 10 degrees is a turn of 20, and 10 to 350 one of -20.
+fn unwrap_counter(args: &Arguments, diff: Difference) -> Difference {
+    match (diff, args.wrap_at, args.wrap_degrees) {
+        (Difference::Number(d), Some(max), _)
+            if d < 0 && d.unsigned_abs() > max.unsigned_abs() / 2 =>
+        {
+            Difference::Number(d + max + 1)
+        }
+        (Difference::Number(d), None, Some(range)) => {
+            let (range, half) = (i128::from(range), i128::from(range / 2));
+            let turn = (i128::from(d) + half).rem_euclid(range) - half;
+            Difference::Number(turn as i64)
+        }
+        _ => diff,
+    }
+}
+
+//Label of the highest severity band reached by the magnitude of the difference, if any
+fn band<'a>(args: &'a Arguments, diff: &Difference) -> Option<&'a str> {
+    let magnitude = diff.abs();
+    args.severity_bands
+        .iter()
+        .rev()
+        .find(|(threshold, _)| magnitude >= *threshold)
+        .map(|(_, label)| label.as_str())
+}
+
+//Whether a difference crosses the gap or the warning threshold, and its severity if the latter
+//is given at all
+fn classify(args: &Arguments, diff: &Difference, gap: &Difference) -> (bool, Option<Severity>) {
+    let crit = match args.expr {
+        Some(ref expr) => expr.eval(diff),
+        None => args.comparison.compare(diff, gap),
+    };
+    match args.warn {
+        Some(ref warn) => match (crit, args.comparison.compare(diff, warn)) {
+            (true, _) => (true, Some(Severity::Crit)),
+            (false, true) => (true, Some(Severity::Warn)),
+            (false, false) => (false, Some(Severity::Ok)),
+        },
+        None => (crit, None),
+    }
+}
+
+//Whether the ratio of a value to the previous one crosses the factor: rising above it for a
+//factor of at least 1, falling below it for a smaller one. A change of sign is always reported,
+//as is any change from zero for a factor of at least 1 (infinite growth), while zero to zero is
+//taken as no change at all.
+fn ratio_crossed(prev: Value, value: Value, factor: f64) -> bool {
+    let (Value::Number(prev), Value::Number(value)) = (prev, value) else {
+        return false;
+    };
+    match (prev, value) {
+        (0, 0) => false,
+        (0, _) => factor >= 1.0,
+        (prev, value) if value != 0 && (prev < 0) != (value < 0) => true,
+        (prev, value) => {
+            let ratio = value as f64 / prev as f64;
+            match factor >= 1.0 {
+                true => ratio > factor,
+                false => ratio < factor,
+            }
+        }
+    }
+}
+
+//Whether a difference of incompatible values is to be reported, None if it is comparable
+fn incomparable(args: &Arguments, n: u64, diff: &Difference) -> Result<Option<bool>, String> {
+    match (diff, &args.incomparable) {
+        (Difference::Incomparable, Incomparable::Refuse) => Err(format!(
+            "line {} value is incompatible with the one compared to",
+            n
+        )),
+        (Difference::Incomparable, Incomparable::Flag) => Ok(Some(true)),
+        (Difference::Incomparable, Incomparable::Skip) => Ok(Some(false)),
+        _ => Ok(None),
+    }
+}
+
+//Detecting gaps in the input according to the arguments, calling back for each one to be
+//reported. Embedders can use this to handle gaps in their own way, instead of text output.
+pub fn detect_gaps<E: Into<Box<dyn Error>>>(
+    mut args: Arguments,
+    reader: &mut dyn BufRead,
+    mut on_gap: impl FnMut(&Gap) -> Result<(), E>,
+) -> Result<Tally, Box<dyn Error>> {
+    sniff_delimiter(&mut args, reader)?;
+    prepare(&mut args)?;
+    detect(&args, reader, None, &mut on_gap)
+}
+
+fn detect<E: Into<Box<dyn Error>>>(
+    args: &Arguments,
+    reader: &mut dyn BufRead,
+    resume: Option<String>,
+    on_gap: &mut impl FnMut(&Gap) -> Result<(), E>,
+) -> Result<Tally, Box<dyn Error>> {
+    //Captured only once, so that results are consistent within a run
+    let now = Value::Timestamp(Utc::now().into());
+
+    struct Previous {
+        n: u64,
+        line: String,
+        value: Value,
+    }
+    let thresholds = match args.thresholds {
+        Some(ref path) => load_thresholds(path, args)?,
+        None => Vec::new(),
+    };
+    let reference = match args.reference {
+        Some(ref path) => load_reference(path, args)?,
+        None => Vec::new(),
+    };
+    //Highest and lowest input value between each two reference values (and before the first
+    //and after the last), for finding the closest ones to each at the end
+    let mut slots: Vec<(Option<Value>, Option<Value>)> = vec![(None, None); reference.len() + 1];
+
+    //State of a series of values, kept separately for each group (or a single unnamed one)
+    #[derive(Default)]
+    struct Series {
+        prev: Option<Previous>,
+        //Previous difference, for comparing the change in differences
+        prev_diff: Option<Difference>,
+        //First non-zero difference, for comparing deviations from it
+        baseline: Option<Difference>,
+        //Exponential moving average of the differences, from the first non-zero one
+        ema: Option<f64>,
+        //Last differences, for comparing to their median
+        median: MedianWindow,
+        sampled: u64,
+        //Last value read, for dropping repeats of it
+        last: Option<Value>,
+        //Start of the current run of values without a gap, in runs mode
+        run: Option<Previous>,
+        //Gaps merged so far, while each is within the coalescing window of the last
+        merged: Option<Merged>,
+    }
+    struct Merged {
+        start: Previous,
+        end: Previous,
+        severity: Option<Severity>,
+    }
+    let mut series: HashMap<String, Series> = HashMap::new();
+
+    //Continuing from the last value of the previous run, taken as if on a virtual line 0
+    if let Some(line) = resume {
+        match parse_line(&line, args) {
+            Ok(Parsed::Value(value)) => {
+                let prev = Previous { n: 0, line, value };
+                series.insert(
+                    String::new(),
+                    Series {
+                        prev: Some(prev),
+                        ..Default::default()
+                    },
+                );
+            }
+            _ => writeln!(
+                std::io::stderr(),
+                "Warning: last line in state is not valid anymore, comparison starts anew.",
+            )?,
+        }
+    }
+
+    //Reporting a run as if it was a gap from its first value to its last
+    fn end_run<E: Into<Box<dyn Error>>>(
+        args: &Arguments,
+        group: &str,
+        state: &mut Series,
+        on_gap: &mut impl FnMut(&Gap) -> Result<(), E>,
+    ) -> Result<(), Box<dyn Error>> {
+        if let (Some(start), Some(ref end)) = (state.run.take(), &state.prev) {
+            let gap = Gap {
+                prev_n: start.n,
+                n: Some(end.n),
+                prev_line: &start.line,
+                line: Some(&end.line),
+                prev_value: start.value,
+                value: end.value,
+                difference: end.value - start.value,
+                severity: None,
+                threshold: None,
+                group: args.group_by.map(|_| group),
+            };
+            on_gap(&gap).map_err(Into::into)?;
+        }
+        Ok(())
+    }
+    //Reporting merged gaps as one, spanning from the start of the first to the end of the last
+    fn end_merged<E: Into<Box<dyn Error>>>(
+        args: &Arguments,
+        group: &str,
+        merged: Option<Merged>,
+        on_gap: &mut impl FnMut(&Gap) -> Result<(), E>,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(Merged {
+            start,
+            end,
+            severity,
+        }) = merged
+        {
+            let gap = Gap {
+                prev_n: start.n,
+                n: Some(end.n),
+                prev_line: &start.line,
+                line: Some(&end.line),
+                prev_value: start.value,
+                value: end.value,
+                difference: end.value - start.value,
+                severity,
+                threshold: None,
+                group: args.group_by.map(|_| group),
+            };
+            on_gap(&gap).map_err(Into::into)?;
+        }
+        Ok(())
+    }
+    //Runs and merged gaps still open, groups in the order of their keys, for a deterministic output
+    fn end_series<E: Into<Box<dyn Error>>>(
+        args: &Arguments,
+        series: &mut HashMap<String, Series>,
+        on_gap: &mut impl FnMut(&Gap) -> Result<(), E>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut groups: Vec<(&String, &mut Series)> = series.iter_mut().collect();
+        groups.sort_by(|a, b| a.0.cmp(b.0));
+        for (group, state) in groups {
+            end_run(args, group, state, on_gap)?;
+            end_merged(args, group, state.merged.take(), on_gap)?;
+        }
+        Ok(())
+    }
+
+    let mut dump = match args.dump_diffs {
+        Some(ref path) => Some(BufWriter::new(
+            File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        )),
+        None => None,
+    };
+    let mut skip_log = match args.skip_log {
+        Some(ref path) => Some(BufWriter::new(
+            File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        )),
+        None => None,
+    };
+    //Recording each line not compared, with its number and the reason
+    let mut log_skip = |n: u64, reason: &dyn std::fmt::Display| match skip_log {
+        Some(ref mut log) => writeln!(log, "{},{}", n, reason),
+        None => Ok(()),
+    };
+
+    //Tallying every difference compared, and dumping it too if requested
+    let mut tally = Tally::default();
+    let mut count = |diff: &Difference, threshold: &Difference| -> std::io::Result<()> {
+        if *diff == Difference::Incomparable {
+            return Ok(());
+        }
+        match diff.partial_cmp(threshold) {
+            Some(std::cmp::Ordering::Greater) => tally.over += 1,
+            Some(std::cmp::Ordering::Equal) => tally.equal += 1,
+            Some(std::cmp::Ordering::Less) => tally.under += 1,
+            None => (),
+        }
+        match dump {
+            //Durations in seconds, without the unit
+            Some(ref mut dump) => {
+                let diff = diff.to_string();
+                writeln!(dump, "{}", diff.strip_suffix('s').unwrap_or(&diff))
+            }
+            None => Ok(()),
+        }
+    };
+
+    let mut other = match args.compare_to {
+        Some(ref path) => Some(ValueReader::open(path)?),
+        None => None,
+    };
+    let mut count_other: u64 = 0;
+
+    //Lowest and highest value (not the first and last, the input may be out of order), and the
+    //number of them, for coverage and rate
+    let mut span: Option<(Value, Value)> = None;
+    let mut actual: u64 = 0;
+    let mut jitter = Jitter::default();
+
+    let mut skipped = false;
+    let mut explained: u64 = 0;
+    let mut handle = |n: u64, line: &str, parsed: Parsed| -> Result<(), Box<dyn Error>> {
+        let value = match parsed {
+            Parsed::Value(value) => value,
+            Parsed::Pair(start, end) => {
+                let diff = unwrap_counter(args, end.difference(start, n)?);
+                count(&diff, &args.difference)?;
+                let (crossed, severity) = classify(args, &diff, &args.difference);
+                let report = matches!(args.mode, Mode::AllDiffs(_)) || crossed;
+                if incomparable(args, n, &diff)?.unwrap_or(report) {
+                    let gap = Gap {
+                        prev_n: n,
+                        n: Some(n),
+                        prev_line: line,
+                        line: None,
+                        prev_value: start,
+                        value: end,
+                        difference: diff,
+                        severity,
+                        threshold: Some(args.difference),
+                        group: None,
+                    };
+                    on_gap(&gap).map_err(Into::into)?;
+                }
+                return Ok(());
+            }
+            Parsed::Comment => {
+                log_skip(n, &"comment")?;
+                return Ok(());
+            }
+            Parsed::Reset => {
+                if matches!(args.mode, Mode::Runs(_)) || args.coalesce.is_some() {
+                    end_series(args, &mut series, on_gap)?;
+                }
+                series.clear();
+                skipped = false;
+                return Ok(());
+            }
+            Parsed::Skipped(reason) => {
+                log_skip(n, &reason)?;
+                skipped = true;
+                return Ok(());
+            }
+        };
+
+        let squeezed = squeezed(line, args);
+        let group = match args.group_by {
+            Some(index) => select_field(&squeezed, index, args)
+                .map_err(|e| format!("line {} {}", n, e))?
+                .unwrap_or_default(),
+            None => "",
+        };
+        if !series.contains_key(group) {
+            series.insert(group.to_string(), Series::default());
+        }
+        let state = series.get_mut(group).unwrap();
+
+        //Dropping repeats of the last value, as if their lines were not there
+        if args.dedupe_key {
+            if state.last.is_some_and(|last| (value - last).is_zero()) {
+                log_skip(n, &"repeat")?;
+                return Ok(());
+            }
+            state.last = Some(value);
+        }
+
+        //Keeping the first value, then every Nth after it
+        if let Some(every) = args.every {
+            state.sampled += 1;
+            if (state.sampled - 1) % every != 0 {
+                return Ok(());
+            }
+        }
+
+        if args.reference.is_some() {
+            let i = reference.partition_point(|(_, _, r)| !(*r - value).is_positive());
+            let (ref mut high, ref mut low) = slots[i];
+            if high.map_or(true, |h| (value - h).is_positive()) {
+                *high = Some(value);
+            }
+            if low.map_or(true, |l| (l - value).is_positive()) {
+                *low = Some(value);
+            }
+            return Ok(());
+        }
+
+        if let Some(ref mut other) = other {
+            let (other_n, other_line, other_value) = match other.next(args)? {
+                Some(next) => next,
+                None => {
+                    return Err(format!(
+                        "line {} has no counterpart, {} ended after {} values",
+                        n,
+                        other.path.display(),
+                        count_other,
+                    )
+                    .into())
+                }
+            };
+            count_other += 1;
+
+            let diff = other_value.difference(value, n)?;
+            let (crossed, severity) = classify(args, &diff, &args.difference);
+            let gap = Gap {
+                prev_n: n,
+                n: Some(other_n),
+                prev_line: line,
+                line: Some(&other_line),
+                prev_value: value,
+                value: other_value,
+                difference: diff,
+                severity,
+                threshold: Some(args.difference),
+                group: None,
+            };
+            count(&gap.difference, &args.difference)?;
+            let report = matches!(args.mode, Mode::AllDiffs(_)) || crossed;
+            if incomparable(args, n, &gap.difference)?.unwrap_or(report) {
+                on_gap(&gap).map_err(Into::into)?;
+            }
+            return Ok(());
+        }
+
+        if let Some(ref stale) = args.stale {
+            let gap = Gap {
+                prev_n: n,
+                n: None,
+                prev_line: line,
+                line: None,
+                prev_value: value,
+                value: now,
+                difference: now - value,
+                severity: None,
+                threshold: Some(*stale),
+                group: None,
+            };
+            count(&gap.difference, stale)?;
+            let report = matches!(args.mode, Mode::AllDiffs(_)) || gap.difference > *stale;
+            if incomparable(args, n, &gap.difference)?.unwrap_or(report) {
+                on_gap(&gap).map_err(Into::into)?;
+            }
+            return Ok(());
+        }
+
+        //Counterpart of the stale check, difference being how far ahead of the current time
+        if let Some(ref max_future) = args.max_future {
+            let gap = Gap {
+                prev_n: n,
+                n: None,
+                prev_line: line,
+                line: None,
+                prev_value: value,
+                value: now,
+                difference: value - now,
+                severity: None,
+                threshold: Some(*max_future),
+                group: None,
+            };
+            count(&gap.difference, max_future)?;
+            let report = matches!(args.mode, Mode::AllDiffs(_)) || gap.difference > *max_future;
+            if incomparable(args, n, &gap.difference)?.unwrap_or(report) {
+                on_gap(&gap).map_err(Into::into)?;
+            }
+            return Ok(());
+        }
+
+        //Expected start is taken as if preceded by one step less, on a virtual line 0
+        if let (None, Some(step), Some(start)) = (&state.prev, args.expect_step, args.expect_start)
+        {
+            state.prev = Some(Previous {
+                n: 0,
+                line: String::new(),
+                value: Value::Number(start - step),
+            });
+        }
+
+        if let Some(ref prev) = state.prev {
+            let diff = unwrap_counter(args, value.difference(prev.value, n)?);
+            //Nothing to compare until the second difference, when comparing their changes
+            let diff = match (args.second_diff, state.prev_diff.replace(diff)) {
+                (true, Some(last)) => Some(diff.checked_sub(last).ok_or_else(|| {
+                    format!(
+                        "line {} change of difference from {} to {} is out of range",
+                        n, last, diff
+                    )
+                })?),
+                (true, None) => None,
+                (false, _) => Some(diff),
+            };
+
+            if let Some(diff) = diff {
+                let gap =
+                    match thresholds.partition_point(|(from, _)| !(*from - value).is_positive()) {
+                        0 => &args.difference,
+                        i => &thresholds[i - 1].1,
+                    };
+                count(&diff, gap)?;
+
+                let modes = (
+                    args.expect_step,
+                    args.baseline_deviation,
+                    args.jitter,
+                    args.ratio,
+                    args.ema,
+                    args.median_window,
+                    args.format_drift,
+                );
+                let (crossed, severity) = match (incomparable(args, n, &diff)?, modes) {
+                    (Some(report), _) => (report, None),
+                    (None, (Some(step), ..)) => (diff != Difference::Number(step), None),
+                    (None, (None, Some(percent), ..)) => match state.baseline {
+                        Some(ref base) => {
+                            let deviation = (diff.as_f64() - base.as_f64()) / base.as_f64();
+                            (deviation.abs() * 100.0 > percent, None)
+                        }
+                        None => {
+                            if diff.as_f64() != 0.0 {
+                                state.baseline = Some(diff);
+                            }
+                            (false, None)
+                        }
+                    },
+                    //Judged by the statistics of the differences before it
+                    (None, (None, None, Some(sigmas), ..)) => {
+                        let x = diff.as_f64();
+                        let outlier = jitter.count >= 2
+                            && (x - jitter.mean).abs() > sigmas * jitter.std_dev();
+                        jitter.add(x);
+                        (outlier, None)
+                    }
+                    (None, (None, None, None, Some(factor), ..)) => {
+                        (ratio_crossed(prev.value, value, factor), None)
+                    }
+                    //Judged by the average before it, which then follows it by alpha
+                    (None, (None, None, None, None, Some(alpha), ..)) => {
+                        let x = diff.as_f64();
+                        match state.ema {
+                            Some(ref mut ema) if *ema != 0.0 => {
+                                let deviation = (x - *ema) / *ema;
+                                *ema += alpha * (x - *ema);
+                                (deviation.abs() * 100.0 > args.ema_tolerance, None)
+                            }
+                            //An average of zero gives no cadence to deviate from, so it starts over
+                            _ => {
+                                if x != 0.0 {
+                                    state.ema = Some(x);
+                                }
+                                (false, None)
+                            }
+                        }
+                    }
+                    //Judged by the median of the window of differences before it, once full. A
+                    //median of zero gives no cadence to deviate from, so nothing is reported then
+                    (None, (None, None, None, None, None, Some(size), _)) => {
+                        let x = diff.as_f64();
+                        let deviation = match state.median.median() {
+                            Some(median) if state.median.order.len() == size && median != 0.0 => {
+                                Some((x - median) / median)
+                            }
+                            _ => None,
+                        };
+                        state.median.push(x, size);
+                        (
+                            deviation.is_some_and(|d| d.abs() * 100.0 > args.median_tolerance),
+                            None,
+                        )
+                    }
+                    //Equal values written differently
+                    (None, (None, None, None, None, None, None, true)) => (
+                        diff.as_f64() == 0.0
+                            && raw_field(&prev.line, args) != raw_field(line, args),
+                        None,
+                    ),
+                    (None, (None, None, None, None, None, None, false)) => {
+                        classify(args, &diff, gap)
+                    }
+                };
+                let threshold = match modes {
+                    (None, None, None, None, None, None, false) => Some(*gap),
+                    _ => None,
+                };
+                let condition = match args.mode {
+                    _ if diff == Difference::Incomparable => crossed,
+                    Mode::AllDiffs(_) => true,
+                    _ if args.mark_skipped && skipped => true,
+                    _ => crossed,
+                };
+                //Narrating the comparison, with the gap only if that was what it was judged by
+                if args.explain.is_some_and(|max| explained < max) {
+                    explained += 1;
+                    let judged = match (modes, &args.expr) {
+                        _ if diff == Difference::Incomparable => String::new(),
+                        ((None, None, None, None, None, None, false), Some(expr)) => format!(
+                            ", which {} the expression {}",
+                            match expr.eval(&diff) {
+                                true => "satisfies",
+                                false => "does not satisfy",
+                            },
+                            expr,
+                        ),
+                        ((None, None, None, None, None, None, false), None) => format!(
+                            ", which is{} {} the gap {}",
+                            match args.comparison.compare(&diff, gap) {
+                                true => "",
+                                false => " not",
+                            },
+                            args.comparison,
+                            gap,
+                        ),
+                        _ => String::new(),
+                    };
+                    writeln!(
+                        std::io::stderr(),
+                        "{}line {} value {} minus line {} value {} = {}{}, so {}",
+                        match args.group_by {
+                            Some(_) => format!("group {}: ", group),
+                            None => String::new(),
+                        },
+                        n,
+                        value,
+                        prev.n,
+                        prev.value,
+                        diff,
+                        judged,
+                        match (condition, severity) {
+                            (true, Some(severity)) => format!("reported as {}", severity),
+                            (true, None) => "reported".to_string(),
+                            (false, _) => "not reported".to_string(),
+                        },
+                    )?;
+                }
+                if condition && matches!(args.mode, Mode::Runs(_)) {
+                    end_run(args, group, state, on_gap)?;
+                } else if let (true, Some(window)) = (condition, &args.coalesce) {
+                    let end = Previous {
+                        n,
+                        line: line.to_string(),
+                        value,
+                    };
+                    match state.merged {
+                        //Measured from the end of the last merged gap to the start of this one
+                        Some(ref mut merged) if prev.value - merged.end.value <= *window => {
+                            merged.end = end;
+                            merged.severity = merged.severity.max(severity);
+                        }
+                        _ => {
+                            let start = Previous {
+                                n: prev.n,
+                                line: prev.line.clone(),
+                                value: prev.value,
+                            };
+                            let merged = state.merged.replace(Merged {
+                                start,
+                                end,
+                                severity,
+                            });
+                            end_merged(args, group, merged, on_gap)?;
+                        }
+                    }
+                } else if condition {
+                    let gap = Gap {
+                        prev_n: prev.n,
+                        n: Some(n),
+                        prev_line: &prev.line,
+                        line: Some(line),
+                        prev_value: prev.value,
+                        value,
+                        difference: diff,
+                        severity,
+                        threshold,
+                        group: args.group_by.map(|_| group),
+                    };
+                    on_gap(&gap).map_err(Into::into)?;
+                }
+            }
+        }
+
+        if args.coverage.is_some() || args.rate.is_some() {
+            span = Some(match span {
+                Some((low, high)) => match ((value - low).as_f64(), (value - high).as_f64()) {
+                    (below, _) if below < 0.0 => (value, high),
+                    (_, above) if above > 0.0 => (low, value),
+                    _ => (low, high),
+                },
+                None => (value, value),
+            });
+            actual += 1;
+        }
+        if matches!(args.mode, Mode::Runs(_)) && state.run.is_none() {
+            state.run = Some(Previous {
+                n,
+                line: line.to_string(),
+                value,
+            });
+        }
+        state.prev = Some(Previous {
+            n,
+            line: line.to_string(),
+            value,
+        });
+        skipped = false;
+        Ok(())
+    };
+
+    process(reader, args, &mut handle)?;
+    if matches!(args.mode, Mode::Runs(_)) || args.coalesce.is_some() {
+        end_series(args, &mut series, on_gap)?;
+    }
+
+    //Reference values are compared to the closest input value below or above them, reported
+    //if the distance crosses the gap, or there was no input value at all
+    let mut above: Vec<Option<Value>> = vec![None; slots.len()];
+    let mut lowest: Option<Value> = None;
+    for (i, (_, low)) in slots.iter().enumerate().rev() {
+        if let Some(low) = low {
+            if lowest.map_or(true, |l| (l - *low).is_positive()) {
+                lowest = Some(*low);
+            }
+        }
+        above[i] = lowest;
+    }
+    let mut below: Option<Value> = None;
+    for (i, (n, line, expected)) in reference.iter().enumerate() {
+        if let Some(high) = slots[i].0 {
+            if below.map_or(true, |b| (high - b).is_positive()) {
+                below = Some(high);
+            }
+        }
+        let closest = match (below, above[i + 1]) {
+            (Some(b), Some(a)) if ((*expected - b) - (a - *expected)).is_positive() => Some(a),
+            (Some(b), _) => Some(b),
+            (None, a) => a,
+        };
+        let (value, difference, distance) = match closest {
+            Some(c) if (c - *expected).is_positive() => (c, c - *expected, c - *expected),
+            Some(c) => (c, c - *expected, *expected - c),
+            None => (
+                *expected,
+                Difference::Incomparable,
+                Difference::Incomparable,
+            ),
+        };
+        count(&distance, &args.difference)?;
+        let (crossed, severity) = classify(args, &distance, &args.difference);
+        if matches!(args.mode, Mode::AllDiffs(_)) || crossed || closest.is_none() {
+            let gap = Gap {
+                prev_n: *n,
+                n: None,
+                prev_line: line,
+                line: None,
+                prev_value: *expected,
+                value,
+                difference,
+                severity,
+                threshold: Some(args.difference),
+                group: None,
+            };
+            on_gap(&gap).map_err(Into::into)?;
+        }
+    }
+
+    if let Some(ref mut other) = other {
+        if let Some((other_n, _, _)) = other.next(args)? {
+            return Err(format!(
+                "input ended after {} values, but {} continues at line {}",
+                count_other,
+                other.path.display(),
+                other_n,
+            )
+            .into());
+        }
+    }
+
+    if let Some(ref mut dump) = dump {
+        dump.flush()?;
+    }
+    if let Some(ref mut log) = skip_log {
+        log.flush()?;
+    }
+    tally.last = series
+        .get("")
+        .and_then(|state| state.prev.as_ref())
+        .map(|prev| prev.line.clone());
+    if args.jitter.is_some() {
+        tally.jitter = Some(jitter);
+    }
+    if let (Some(cadence), Some((low, high))) = (args.coverage, span) {
+        let span = high - low;
+        tally.coverage = Some(Coverage {
+            span,
+            expected: (span.as_f64() / cadence.as_f64()).round().max(0.0) as u64 + 1,
+            actual,
+        });
+    }
+    if let (Some(unit), Some((low, high))) = (args.rate, span) {
+        tally.rate = Some(Rate {
+            span: high - low,
+            values: actual,
+            unit,
+        });
+    }
+    Ok(tally)
+}
+
+//Writing metrics in Prometheus text format, through a temporary file and renaming it,
+//so that the textfile collector never reads a partial one, see README
+fn write_prometheus(
+    path: &PathBuf,
+    args: &Arguments,
+    tally: &Tally,
+    gaps: u64,
+    max_gap: Option<Difference>,
+) -> std::io::Result<()> {
+    let file = args
+        .path
+        .to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    let (name, unit) = match args.format {
+        Format::UInt | Format::Int => ("csv_max_gap", ""),
+        _ => ("csv_max_gap_seconds", ", in seconds"),
+    };
+    let max_gap = max_gap.map_or(0.0, |d| d.as_f64().abs());
+
+    let mut tmp = path.clone().into_os_string();
+    tmp.push(".tmp");
+    let mut out = BufWriter::new(File::create(&tmp)?);
+    writeln!(out, "# HELP csv_gaps Number of gaps reported in the run.")?;
+    writeln!(out, "# TYPE csv_gaps gauge")?;
+    writeln!(out, "csv_gaps{{file=\"{}\"}} {}", file, gaps)?;
+    writeln!(
+        out,
+        "# HELP csv_differences Number of differences compared in the run."
+    )?;
+    writeln!(out, "# TYPE csv_differences gauge")?;
+    writeln!(
+        out,
+        "csv_differences{{file=\"{}\"}} {}",
+        file,
+        tally.over + tally.equal + tally.under
+    )?;
+    writeln!(
+        out,
+        "# HELP {} Largest magnitude of the gaps reported in the run{}.",
+        name, unit
+    )?;
+    writeln!(out, "# TYPE {} gauge", name)?;
+    writeln!(out, "{}{{file=\"{}\"}} {}", name, file, max_gap)?;
+    out.into_inner()?.sync_all()?;
+    //Not leaving the partial file behind
+    std::fs::rename(&tmp, path).map_err(|e| {
+        std::fs::remove_file(&tmp).ok();
+        e
+    })
+}
+
+//Where the previous run ended, and the line of its last value if any
+struct State {
+    offset: u64,
+    last: Option<String>,
+}
+
+//Loading the offset and the last line of the previous run, see write_state. A missing state
+//file means starting from the beginning, as does an invalid one or an input shorter than the
+//offset (e.g. after rotation), but with a warning.
+fn load_state(path: &PathBuf, input: &PathBuf) -> Result<Option<State>, Box<dyn Error>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) if e.kind() != std::io::ErrorKind::InvalidData => {
+            return Err(format!("{}: {}", path.display(), e).into())
+        }
+        Err(_) => String::new(),
+    };
+    let mut lines = content.lines();
+    let offset = match lines.next().map(|offset| offset.parse::<u64>()) {
+        Some(Ok(offset)) => offset,
+        _ => {
+            writeln!(
+                std::io::stderr(),
+                "Warning: state file {} is invalid, starting from the beginning.",
+                path.display(),
+            )?;
+            return Ok(None);
+        }
+    };
+    let len = std::fs::metadata(input)
+        .map_err(|e| format!("{}: {}", input.display(), e))?
+        .len();
+    if offset > len {
+        writeln!(
+            std::io::stderr(),
+            "Warning: {} is shorter than in state, starting from the beginning.",
+            input.display(),
+        )?;
+        return Ok(None);
+    }
+    Ok(Some(State {
+        offset,
+        last: lines.next().map(|line| line.to_string()),
+    }))
+}
+
+//Saving the offset where the run ended, and the line of the last value if any, through a
+//temporary file like write_prometheus. The offset is that of the end of the last complete line,
+//a last line without a newline is not read until it gets one.
+fn write_state(path: &PathBuf, offset: u64, last: Option<&str>) -> std::io::Result<()> {
+    let mut tmp = path.clone().into_os_string();
+    tmp.push(".tmp");
+    let mut out = BufWriter::new(File::create(&tmp)?);
+    writeln!(out, "{}", offset)?;
+    if let Some(line) = last {
+        writeln!(out, "{}", line)?;
+    }
+    out.into_inner()?.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+//Opening input file, retrying while it does not exist yet for the given seconds, if any
+fn open_waiting(path: &PathBuf, wait: Option<u64>) -> std::io::Result<File> {
+    let deadline = wait.map(|secs| Instant::now() + std::time::Duration::from_secs(secs));
+    loop {
+        match File::open(path) {
+            Err(e)
+                if e.kind() == std::io::ErrorKind::NotFound
+                    && deadline.is_some_and(|deadline| Instant::now() < deadline) =>
+            {
+                std::thread::sleep(std::time::Duration::from_millis(100))
+            }
+            result => return result,
+        }
+    }
+}
+
+//Seeking to the given offset, then skipping the rest of the line if it was not started there
+fn seek_line(file: File, offset: u64) -> Result<BufReader<File>, Box<dyn Error>> {
+    let len = file.metadata()?.len();
+    if offset > len {
+        return Err(format!(
+            "offset {} is beyond the end of file ({} bytes)",
+            offset, len
+        )
+        .into());
+    }
+    let mut reader = BufReader::new(file);
+    if offset > 0 {
+        reader.seek(SeekFrom::Start(offset - 1))?;
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        if byte[0] != b'\n' {
+            reader.read_until(b'\n', &mut Vec::new())?;
+        }
+    }
+    Ok(reader)
+}
+
+//Position of the last newline in the bytes, if any
+fn newline(bytes: &[u8]) -> Option<usize> {
+    bytes.iter().rposition(|b| *b == b'\n')
+}
+
+//Keeping count of the bytes consumed from the input, starting from where it was opened
+struct Counted<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+    //Count up to the end of the last complete line, after its newline
+    line_end: Rc<Cell<u64>>,
+    bar: Option<Bar>,
+}
+impl<R> Counted<R> {
+    //Bytes added, with the position of the last newline among them, if any
+    fn add(&mut self, len: u64, newline: Option<usize>) {
+        if let Some(i) = newline {
+            self.line_end.set(self.count.get() + i as u64 + 1);
+        }
+        self.count.set(self.count.get() + len);
+        if let Some(ref mut bar) = self.bar {
+            bar.update(self.count.get());
+        }
+    }
+}
+impl<R: BufRead> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.add(len as u64, newline(&buf[..len]));
+        Ok(len)
+    }
+}
+impl<R: BufRead> BufRead for Counted<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    //Bytes to be consumed are still in the buffer, so getting it again does not read any more
+    fn consume(&mut self, amt: usize) {
+        let newline = match amt {
+            0 => None,
+            _ => self
+                .inner
+                .fill_buf()
+                .ok()
+                .and_then(|buf| newline(&buf[..amt.min(buf.len())])),
+        };
+        self.inner.consume(amt);
+        self.add(amt as u64, newline);
+    }
+}
+
+//Progress bar on STDERR, by the bytes consumed relative to the length of the file at the start,
+//redrawn at most ten times a second and erased when reading is done
+struct Bar {
+    length: u64,
+    drawn: Option<Instant>,
+}
+impl Bar {
+    const WIDTH: usize = 40;
+
+    fn update(&mut self, count: u64) {
+        if self.drawn.is_some_and(|t| t.elapsed().as_millis() < 100) {
+            return;
+        }
+        self.drawn = Some(Instant::now());
+        let ratio = match self.length {
+            0 => 1.0,
+            length => (count as f64 / length as f64).min(1.0),
+        };
+        let filled = (ratio * Self::WIDTH as f64) as usize;
+        //Not essential, so failing to draw is not an error
+        write!(
+            std::io::stderr(),
+            "\r[{}{}] {:5.1}%",
+            "#".repeat(filled),
+            " ".repeat(Self::WIDTH - filled),
+            ratio * 100.0
+        )
+        .ok();
+    }
+}
+impl Drop for Bar {
+    fn drop(&mut self) {
+        if self.drawn.is_some() {
+            write!(std::io::stderr(), "\r{:1$}\r", "", Self::WIDTH + 9).ok();
+        }
+    }
+}
+
+//Amount of input read at once on the reading thread, and how many of these it may be ahead
+const READ_AHEAD_BLOCK: usize = 1 << 16;
+const READ_AHEAD_BLOCKS: usize = 16;
+
+//Reading input on a separate thread, so that waiting for IO (e.g. on a network filesystem)
+//overlaps with parsing and comparing on this one. Blocks are passed on in order through a
+//bounded channel, so everything after reading is the same as without it.
+struct ReadAhead {
+    blocks: std::sync::mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    block: Vec<u8>,
+    pos: usize,
+}
+impl ReadAhead {
+    fn spawn(mut inner: impl Read + Send + 'static) -> Self {
+        let (sender, blocks) = std::sync::mpsc::sync_channel(READ_AHEAD_BLOCKS);
+        std::thread::spawn(move || loop {
+            let mut block = vec![0; READ_AHEAD_BLOCK];
+            let result = match inner.read(&mut block) {
+                Ok(0) => break,
+                Ok(len) => {
+                    block.truncate(len);
+                    Ok(block)
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            //Receiver is dropped if processing stopped early, e.g. on an invalid line
+            if sender.send(result).is_err() || failed {
+                break;
+            }
+        });
+        Self {
+            blocks,
+            block: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+impl BufRead for ReadAhead {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.pos == self.block.len() {
+            //Channel is closed by the reading thread at the end of input, or after an error
+            match self.blocks.recv() {
+                Ok(block) => {
+                    self.block = block?;
+                    self.pos = 0;
+                }
+                Err(_) => return Ok(&[]),
+            }
+        }
+        Ok(&self.block[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.block.len());
+    }
+}
+
+//Stdin is buffered by itself, so that lines sampled from it are read again afterwards, but only
+//if nothing more is read from it than sampled: reading ahead on a thread is not allowed then
+fn open_input(args: &Arguments, read_ahead: bool) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    let read_ahead = read_ahead && args.threads_for_parse;
+    Ok(match (args.path.as_os_str() == "-", args.seek) {
+        (true, Some(_)) => return Err("Cannot seek on STDIN".into()),
+        (true, None) if args.position_percent => {
+            return Err("Cannot report positions on STDIN".into())
+        }
+        (true, None) if read_ahead => Box::new(ReadAhead::spawn(std::io::stdin())),
+        (true, None) => Box::new(std::io::stdin().lock()),
+        (false, seek) => {
+            let file = open_waiting(&args.path, args.wait_for_file)?;
+            let reader = seek_line(file, seek.unwrap_or(0))
+                .map_err(|e| format!("{}: {}", args.path.display(), e))?;
+            match read_ahead {
+                true => Box::new(ReadAhead::spawn(reader)),
+                false => Box::new(reader),
+            }
+        }
+    })
+}
+
+//Printing the lines around the largest gap, reading the input again from the start, numbered
+//like grep does: ':' after the lines of the gap, '-' after the others
+fn write_peek(
+    args: &Arguments,
+    context: u64,
+    (prev_n, n): (u64, Option<u64>),
+    difference: Difference,
+) -> Result<(), Box<dyn Error>> {
+    let n = n.unwrap_or(prev_n);
+    let (first, last) = (prev_n.saturating_sub(context), n.saturating_add(context));
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    writeln!(
+        out,
+        "Largest difference {} between lines {} and {}:",
+        difference, prev_n, n
+    )?;
+    let mut reader = open_input(args, true)?;
+    let mut bytes = Vec::new();
+    let mut buf = String::new();
+    let mut i: u64 = 0;
+    while i < last
+        && read_line(&mut *reader, &mut bytes, &mut buf, args)
+            .map_err(|e| format!("line {} {}", i + 1, e))?
+            > 0
+    {
+        i += 1;
+        if i >= first {
+            let mark = match i == prev_n || i == n {
+                true => ':',
+                false => '-',
+            };
+            writeln!(out, "{}{}{}", i, mark, buf.trim_end_matches(['\r', '\n']))?;
+        }
+        buf.clear();
+    }
+    Ok(out.flush()?)
+}
+
+pub fn csv_detect_missing(mut args: Arguments) -> Result<(), Box<dyn Error>> {
+    //Continuing where the previous run ended, if there was one
+    let resume = match args.state {
+        Some(_) if args.path.as_os_str() == "-" => return Err("Cannot keep state on STDIN".into()),
+        Some(_) if args.seek.is_some() => {
+            return Err("state cannot be kept when seeking, it sets the offset itself".into())
+        }
+        Some(ref path) => load_state(path, &args.path)?,
+        None => None,
+    };
+    let last = match resume {
+        Some(State { offset, last }) => {
+            args.seek = Some(offset);
+            last
+        }
+        None => None,
+    };
+    let mut reader = open_input(&args, true)?;
+
+    //Bytes of the input consumed, for the position of gaps relative to the length of the file
+    //at the start, for the progress bar, and for the offset saved in the state. The bar is only
+    //drawn to a terminal, and not for STDIN, of which the length is not known.
+    let consumed = Rc::new(Cell::new(args.seek.unwrap_or(0)));
+    let line_end = Rc::new(Cell::new(consumed.get()));
+    let bar = args.bar && args.path.as_os_str() != "-" && std::io::stderr().is_terminal();
+    let length = match args.position_percent || bar {
+        true => Some(
+            std::fs::metadata(&args.path)
+                .map_err(|e| format!("{}: {}", args.path.display(), e))?
+                .len(),
+        ),
+        false => None,
+    };
+    if args.position_percent || args.state.is_some() || bar {
+        reader = Box::new(Counted {
+            inner: reader,
+            count: consumed.clone(),
+            line_end: line_end.clone(),
+            bar: match (bar, length) {
+                (true, Some(length)) => Some(Bar {
+                    length,
+                    drawn: None,
+                }),
+                _ => None,
+            },
+        });
+    }
+    let length = length.filter(|_| args.position_percent);
+    let position = || {
+        length.map(|length| match length {
+            0 => 100.0,
+            _ => (consumed.get() as f64 / length as f64 * 100.0).min(100.0),
+        })
+    };
+
+    sniff_delimiter(&mut args, &mut reader)?;
+    prepare(&mut args)?;
+
+    //Nothing is written when linting, the first gap ends the run as an error instead, nor when
+    //counting gaps by day or peeking, only the counts or the largest gap at the end
+    let mut targets: Vec<(PathBuf, Mode)> = match (
+        args.lint || args.by_day || args.peek.is_some(),
+        args.outputs.is_empty(),
+    ) {
+        (true, _) => Vec::new(),
+        (false, true) => vec![("-".into(), args.mode.clone())],
+        (false, false) => args.outputs.clone(),
+    };
+    let stdout_targets = targets.iter().filter(|(path, _)| path.as_os_str() == "-");
+    if stdout_targets.clone().count() > 1 {
+        return Err("only one output can be written to STDOUT".into());
+    }
+    if stdout_targets
+        .clone()
+        .any(|(_, mode)| matches!(mode, Mode::Binary))
+        && std::io::stdout().is_terminal()
+    {
+        return Err("binary output cannot be written to a terminal".into());
+    }
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    if stdout_targets.clone().count() > 0
+        && args.compress.is_some()
+        && std::io::stdout().is_terminal()
+    {
+        return Err("compressed output cannot be written to a terminal".into());
+    }
+    if args.redact && targets.iter().any(|(_, mode)| matches!(mode, Mode::Filter)) {
+        writeln!(
+            std::io::stderr(),
+            "Warning: filter mode outputs whole lines, these cannot be redacted.",
+        )?;
+    }
+
+    //Replacing standard output if available, falling back to it otherwise
+    #[cfg(all(unix, feature = "syslog"))]
+    let mut syslog = match args.syslog.map(Syslog::connect) {
+        Some(Ok(syslog)) => {
+            targets.clear();
+            Some(syslog)
+        }
+        Some(Err(e)) => {
+            writeln!(
+                std::io::stderr(),
+                "Warning: syslog is unavailable ({}), writing to STDOUT instead.",
+                e
+            )?;
+            None
+        }
+        None => None,
+    };
+    //Messages that could not be sent are not lost, but written to STDERR
+    #[cfg(all(unix, feature = "syslog"))]
+    let mut send = |gap: &Gap| match syslog {
+        Some(ref mut syslog) => {
+            let message = gap_message(&args, gap);
+            syslog
+                .send(&message)
+                .or_else(|_| writeln!(std::io::stderr(), "{}", message))
+        }
+        None => Ok(()),
+    };
+    //Sent in addition to the output, not instead of it
+    #[cfg(feature = "webhook")]
+    let mut batch = args
+        .webhook
+        .as_ref()
+        .map(|webhook| webhook::Batch::new(webhook, args.webhook_batch));
+
+    struct Sink {
+        out: Output,
+        mode: Mode,
+        written: u64,
+    }
+    let mut sinks: Vec<Sink> = Vec::new();
+    for (path, mode) in targets.drain(..) {
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some(compress) = compression(&path, args.compress)? {
+            let inner: Box<dyn Write> = match path.as_os_str() == "-" {
+                true => Box::new(std::io::stdout().lock()),
+                false => {
+                    Box::new(File::create(&path).map_err(|e| format!("{}: {}", path.display(), e))?)
+                }
+            };
+            sinks.push(Sink {
+                out: Output::Compressed(BufWriter::new(compress.encoder(inner)?)),
+                mode,
+                written: 0,
+            });
+            continue;
+        }
+        //Stdout is line buffered by itself, block buffering needs a wrapper
+        let out: Box<dyn Write> = match path.as_os_str() == "-" {
+            true => {
+                let stdout = std::io::stdout();
+                match (&args.buffering, stdout.is_terminal()) {
+                    (Buffering::Line, _) | (Buffering::Auto, true) => Box::new(stdout.lock()),
+                    (Buffering::Block, _) | (Buffering::Auto, false) => {
+                        Box::new(BufWriter::new(stdout.lock()))
+                    }
+                }
+            }
+            false => {
+                let file = File::create(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                match args.buffering {
+                    Buffering::Line => Box::new(std::io::LineWriter::new(file)),
+                    Buffering::Block | Buffering::Auto => Box::new(BufWriter::new(file)),
+                }
+            }
+        };
+        sinks.push(Sink {
+            out: Output::Plain(out),
+            mode,
+            written: 0,
+        });
+    }
+    #[cfg(all(unix, feature = "socket"))]
+    if let Some(ref path) = args.socket {
+        sinks[0].out = Output::Plain(open_socket(path, args.socket_listen, &args.buffering)?);
+    }
+    for sink in sinks.iter_mut() {
+        write_header(&mut sink.out, &args, &sink.mode)?;
+    }
+
+    //Diagnostic output of parsed values only, without any comparison
+    if let Mode::Echo(ref delim) = args.mode {
+        let out = &mut sinks[0].out;
+        let result = process(&mut reader, &args, &mut |n, _, parsed| {
+            match parsed {
+                Parsed::Value(value) => writeln!(out, "{}{}{}", n, delim, value)?,
+                Parsed::Pair(start, end) => {
+                    writeln!(out, "{}{}{}{}{}", n, delim, start, delim, end)?
+                }
+                Parsed::Comment | Parsed::Reset | Parsed::Skipped(_) => (),
+            }
+            Ok(())
+        });
+        for sink in sinks.drain(..) {
+            sink.out.finish()?;
+        }
+        return result;
+    }
+
+    //Owned copy of a gap, for sorting them all at the end
+    struct Buffered {
+        prev_n: u64,
+        n: Option<u64>,
+        prev_line: String,
+        line: Option<String>,
+        prev_value: Value,
+        value: Value,
+        difference: Difference,
+        severity: Option<Severity>,
+        threshold: Option<Difference>,
+        group: Option<String>,
+        position: Option<f64>,
+    }
+    let mut buffered: Vec<Buffered> = Vec::new();
+    let mut buffered_bytes: u64 = 0;
+    let mut gaps: u64 = 0;
+    let mut max_gap: Option<Difference> = None;
+    //Lines of the largest gap, for peeking
+    let mut max_lines: (u64, Option<u64>) = (0, None);
+    //Gaps counted by the calendar day of their start, in its own offset
+    let mut days: BTreeMap<chrono::NaiveDate, u64> = BTreeMap::new();
+
+    let tally = detect(&args, &mut reader, last, &mut |gap: &Gap| {
+        if args.lint {
+            return Err(format!(
+                "line {} {}",
+                gap.n.unwrap_or(gap.prev_n),
+                gap_message(&args, gap)
+            )
+            .into());
+        }
+        gaps += 1;
+        if max_gap.map_or(true, |m| gap.difference.cmp_magnitude_desc(&m).is_lt()) {
+            max_gap = Some(gap.difference);
+            max_lines = (gap.prev_n, gap.n);
+        }
+        if args.by_day {
+            if let Value::Timestamp(t) = gap.prev_value {
+                *days.entry(t.date_naive()).or_default() += 1;
+            }
+            return Ok(());
+        }
+        if args.peek.is_some() {
+            return Ok(());
+        }
+        match args.sort_by_gap || args.rank {
+            true => {
+                buffered_bytes += (std::mem::size_of::<Buffered>()
+                    + gap.prev_line.len()
+                    + gap.line.map_or(0, |l| l.len())
+                    + gap.group.map_or(0, |g| g.len())) as u64;
+                if args.max_buffer_bytes > 0 && buffered_bytes > args.max_buffer_bytes {
+                    return Err(buffer_exceeded(&args).into());
+                }
+                buffered.push(Buffered {
+                    prev_n: gap.prev_n,
+                    n: gap.n,
+                    prev_line: gap.prev_line.to_string(),
+                    line: gap.line.map(|l| l.to_string()),
+                    prev_value: gap.prev_value,
+                    value: gap.value,
+                    difference: gap.difference,
+                    severity: gap.severity,
+                    threshold: gap.threshold,
+                    group: gap.group.map(|g| g.to_string()),
+                    position: position(),
+                })
+            }
+            false => {
+                for sink in sinks.iter_mut() {
+                    write_gap(
+                        &mut sink.out,
+                        &args,
+                        &sink.mode,
+                        gap,
+                        sink.written,
+                        position(),
+                    )?;
+                    sink.written += 1;
+                }
+                #[cfg(all(unix, feature = "syslog"))]
+                send(gap)?;
+                #[cfg(feature = "webhook")]
+                if let Some(ref mut batch) = batch {
+                    notify(batch, &args, gap, position())?;
+                }
+            }
+        }
+        Ok::<(), Box<dyn Error>>(())
+    });
+    let tally = match tally {
+        Ok(tally) => tally,
+        Err(e) => {
+            for sink in sinks.drain(..) {
+                sink.out.finish()?;
+            }
+            #[cfg(feature = "webhook")]
+            if let Some(ref mut batch) = batch {
+                batch.flush()?;
+            }
+            return Err(e);
+        }
+    };
+
+    //Stable, so that equal gaps remain in input order
+    buffered.sort_by(|a, b| a.difference.cmp_magnitude_desc(&b.difference));
+    for b in buffered {
+        let gap = Gap {
+            prev_n: b.prev_n,
+            n: b.n,
+            prev_line: &b.prev_line,
+            line: b.line.as_deref(),
+            prev_value: b.prev_value,
+            value: b.value,
+            difference: b.difference,
+            severity: b.severity,
+            threshold: b.threshold,
+            group: b.group.as_deref(),
+        };
+        for sink in sinks.iter_mut() {
+            write_gap(
+                &mut sink.out,
+                &args,
+                &sink.mode,
+                &gap,
+                sink.written,
+                b.position,
+            )?;
+            sink.written += 1;
+        }
+        #[cfg(all(unix, feature = "syslog"))]
+        send(&gap)?;
+        #[cfg(feature = "webhook")]
+        if let Some(ref mut batch) = batch {
+            notify(batch, &args, &gap, b.position)?;
+        }
+    }
+
+    for sink in sinks.drain(..) {
+        sink.out.finish()?;
+    }
+    #[cfg(feature = "webhook")]
+    if let Some(ref mut batch) = batch {
+        batch.flush()?;
+    }
+    if let (true, Mode::Diff(ref delim)) = (args.by_day, &args.mode) {
+        let mut out = std::io::stdout().lock();
+        for (day, count) in days.iter() {
+            writeln!(out, "{}{}{}", day, delim, count)?;
+        }
+    }
+    if let (Some(context), Some(difference)) = (args.peek, max_gap) {
+        drop(reader);
+        write_peek(&args, context, max_lines, difference)?;
+    }
+    if let Some(ref path) = args.prometheus {
+        write_prometheus(path, &args, &tally, gaps, max_gap)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    if let Some(ref path) = args.state {
+        write_state(path, line_end.get(), tally.last.as_deref())
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    if args.tally {
+        writeln!(
+            std::io::stderr(),
+            "over: {}\nequal: {}\nunder: {}",
+            tally.over,
+            tally.equal,
+            tally.under,
+        )?;
+    }
+    if let Some(jitter) = tally.jitter {
+        let unit = match args.format {
+            Format::UInt | Format::Int => "",
+            _ => "s",
+        };
+        writeln!(
+            std::io::stderr(),
+            "differences: {}\nmean: {:.3}{}\nstd-dev: {:.3}{}",
+            jitter.count,
+            jitter.mean,
+            unit,
+            jitter.std_dev(),
+            unit,
+        )?;
+    }
+    if let Some(coverage) = tally.coverage {
+        writeln!(
+            std::io::stderr(),
+            "span: {}\nexpected: {}\nactual: {}\ncoverage: {:.2}%",
+            coverage.span,
+            coverage.expected,
+            coverage.actual,
+            coverage.percent(),
+        )?;
+    }
+    if let Some(rate) = tally.rate {
+        match rate.per_unit() {
+            Some(per_unit) => writeln!(std::io::stderr(), "rate: {:.3}/{}", per_unit, rate.unit)?,
+            None => writeln!(std::io::stderr(), "rate: NaN")?,
+        }
     }
 
     Ok(())
@@ -11,7 +11,7 @@ use csv_detect_missing::*;
 use std::io::IsTerminal;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let arg_matches = clap::Command::new("csv-detect-missing")
+    let command = clap::Command::new("csv-detect-missing")
         .version(clap::crate_version!())
         .after_long_help(
             "Created by Zoltan Kovari, 2024. Licensed under the Apache License, Version 2.0",
@@ -34,9 +34,52 @@ the whole line being treated as one field.",
                 )
                 .num_args(1)
                 .value_name("DELIM")
+                .env("CSVDM_DELIM")
                 .value_parser(clap::value_parser!(String))
                 .default_value(","),
         )
+        .arg(
+            clap::Arg::new("auto-delim")
+                .long("auto-delim")
+                .help("Detect input delimiter from the first lines")
+                .long_help(
+                    "Detect the input delimiter from the first few lines (other than
+comments and empty lines), choosing the most consistent one of comma,
+tab, semicolon and pipe. Falls back to comma with a warning if
+ambiguous. An explicitly given -d (or CSVDM_DELIM) takes precedence.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["field-width"]),
+        )
+        .arg(
+            clap::Arg::new("squeeze")
+                .long("squeeze")
+                .help("Collapse runs of whitespace before splitting fields")
+                .long_help(
+                    "Collapse every run of whitespace (spaces and tabs alike) in the line to
+a single space, and remove it from the start and the end of the line,
+before splitting the fields. Useful with a space delimiter for
+irregularly spaced input. Comments are recognized before this, and
+lines are still output unchanged.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("field-width"),
+        )
+        .arg(
+            clap::Arg::new("delim-any")
+                .long("delim-any")
+                .help("Split on any of these characters, instead of delimiter")
+                .long_help(
+                    "Set of characters, any of which separates the input fields, e.g. \",;\"
+for lines using either a comma or a semicolon. Used instead of -d, with
+the first character taken as the input delimiter otherwise (e.g. for
+diff output). \"\\t\" is also accepted for Tabulator.",
+                )
+                .num_args(1)
+                .value_name("CHARS")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all(["delimiter", "field-width", "auto-delim"]),
+        )
         .arg(
             clap::Arg::new("index")
                 .short('i')
@@ -44,9 +87,104 @@ the whole line being treated as one field.",
                 .long_help("Index of the field to be parsed and evaluated, starting from 1.")
                 .num_args(1)
                 .value_name("INDEX")
+                .env("CSVDM_INDEX")
                 .value_parser(clap::value_parser!(u16).range(1..))
                 .default_value("1"),
         )
+        .arg(
+            clap::Arg::new("minus-index")
+                .long("minus-index")
+                .help("Within-row mode: subtract field at this index")
+                .long_help(
+                    "Within-row mode: instead of subsequent lines, the field at this index
+is subtracted from the one at --index on each line (e.g. start and end
+timestamps), comparing the difference to the gap. Both fields must be
+valid under the format.",
+                )
+                .num_args(1)
+                .value_name("INDEX")
+                .value_parser(clap::value_parser!(u16).range(1..))
+                .conflicts_with_all([
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "mark-skipped",
+                    "reset-on",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("from-columns")
+                .long("from-columns")
+                .help("Join the fields at these indices into the value")
+                .long_help(
+                    "Instead of the field at --index, join the fields at the given indices
+(comma separated, like \"1,2\") in that order with --join-with, and
+parse the result as the value, e.g. a date and a time in separate
+columns. Every field must be present, or the line is handled as one
+with a missing field (see -a and --field-default).",
+                )
+                .num_args(1)
+                .value_name("INDICES")
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(u16).range(1..))
+                .conflicts_with_all(["index", "minus-index"]),
+        )
+        .arg(
+            clap::Arg::new("join-with")
+                .long("join-with")
+                .help("Separator between joined fields")
+                .long_help("Separator put between the fields joined by --from-columns.")
+                .num_args(1)
+                .value_name("SEPARATOR")
+                .default_value(" ")
+                .requires("from-columns"),
+        )
+        .arg(
+            clap::Arg::new("exact-fields")
+                .long("exact-fields")
+                .help("Expected number of fields in every line")
+                .long_help(
+                    "Strict validation of the number of fields: it is an error if a line
+(other than a comment or an empty line) has more or less fields than
+given, even with -a.",
+                )
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u16).range(1..))
+                .conflicts_with("field-width"),
+        )
+        .arg(
+            clap::Arg::new("group-by")
+                .long("group-by")
+                .help("Compare values within groups keyed by field at this index")
+                .long_help(
+                    "Compare values only within groups of lines having the same key in the
+field at this index (e.g. a device ID), as if each group was a
+separate input. Interleaved series can be checked this way in one go.
+Reported gaps are annotated with their key: in diff and all-diffs
+modes it precedes the values, in CSV mode it is the first column, in
+JSON mode a \"group\" member, and in missing mode it precedes every
+value with a comma. Binary output cannot be annotated.",
+                )
+                .num_args(1)
+                .value_name("INDEX")
+                .value_parser(clap::value_parser!(u16).range(1..))
+                .conflicts_with_all(["stale", "max-future", "compare-to", "minus-index"]),
+        )
+        .arg(
+            clap::Arg::new("field-width")
+                .short('w')
+                .long("field-width")
+                .help("Fixed field width, instead of delimiter")
+                .long_help(
+                    "Width of fixed width fields in characters, used instead of the
+delimiter to separate input fields. The field is still selected by
+index, and leading and trailing whitespace is ignored when parsing.",
+                )
+                .num_args(1)
+                .value_name("WIDTH")
+                .value_parser(clap::value_parser!(u16).range(1..)),
+        )
         .arg(
             clap::Arg::new("format")
                 .short('f')
@@ -55,16 +193,86 @@ the whole line being treated as one field.",
                     "Format of the selected field, with the following options supported:
     uint: Unsigned integer value.
     int: Signed integer value.
+        (Both accept an explicit \"+\" sign and leading zeros.)
     unix: Non-leap seconds passed since the Unix Epoch.
     unix_ms: Similar to 'unix' but in milliseconds.
-    rfc-3339: Timestamp like \"yyyy-mm-ddTHH:MM:SSZ\".",
+    unix_frac: Similar to 'unix' but with optional fraction of seconds,
+        like \"1700000000.482\" (up to nanoseconds).
+    hex_unix_ms: Similar to 'unix_ms' but hexadecimal, with optional \"0x\"
+        prefix, like \"0x0001E240\" (e.g. milliseconds since boot).
+    rfc-3339: Timestamp like \"yyyy-mm-ddTHH:MM:SSZ\", also accepting a
+        comma before the fraction of seconds, like \"00:00:00,500Z\".
+    named-month: Timestamp like \"01 Jan 2024 13:00:00\", with English month
+        names (abbreviated or full), four-digit year and optional
+        offset like \"+0100\" (UTC if omitted).
+    isoweek: ISO 8601 week date like \"2024-W03-1\" (year, week 1-53 and
+        day 1-7 from Monday), taken as midnight UTC.
+    auto-time: Any of several common timestamp layouts, tried in this
+        order for each field: rfc-3339, \"yyyy-mm-dd HH:MM:SS\" (or with
+        \"T\"), \"yyyy/mm/dd HH:MM:SS\" (both with optional fraction of
+        seconds), \"yyyy-mm-dd\", \"yyyy/mm/dd\" (midnight), all UTC if
+        without offset, and finally numbers since the Unix Epoch, in
+        seconds below 100000000000, in milliseconds otherwise. Numbers of
+        11 or 12 digits are reported as ambiguous with --verbose.
+    duration: Elapsed time in the same syntax as time gaps, like \"1h30m\",
+        with optional fraction of seconds, like \"240.07s\".
+A comma separated list, like \"rfc-3339,unix\", tries the formats in
+the given order for each field, stopping at the first that succeeds.
+Formats in the list must yield comparable values (e.g. timestamps),
+and the gap syntax follows the first one.",
                 )
                 .num_args(1)
                 .value_name("FORMAT")
-                .value_parser(["uint", "int", "unix", "unix_ms", "rfc-3339"])
-                .hide_possible_values(true)
+                .env("CSVDM_FORMAT")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
                 .default_value("uint"),
         )
+        .arg(
+            clap::Arg::new("incomparable")
+                .long("incomparable")
+                .help("Allow incompatible fallback formats [ACTION: flag, skip]")
+                .long_help(
+                    "Allow fallback formats incompatible with the first one (e.g.
+\"rfc-3339,int\"), whose values cannot be compared to each other. Such
+differences are shown as \"NaN\", and are either reported as if
+crossing the gap (\"flag\"), or skipped (\"skip\"), but never counted in
+the tally. By default, such formats are refused.",
+                )
+                .num_args(1)
+                .value_name("ACTION")
+                .value_parser(["flag", "skip"]),
+        )
+        .arg(
+            clap::Arg::new("probe")
+                .long("probe")
+                .help("Guess the format from the first values [ACTION: print, use]")
+                .long_help(
+                    "Guess the format of the selected field from the first few values,
+by how many of them each format can parse, and report it to STDERR.
+Integers are told apart by magnitude: those of 9 to 11 digits are
+taken as unix, of 12 to 14 digits as unix_ms. With \"print\" (default)
+the program stops there, with \"use\" it goes on with the guessed
+format, with the gap given accordingly.",
+                )
+                .num_args(0..=1)
+                .require_equals(true)
+                .value_name("ACTION")
+                .value_parser(["print", "use"])
+                .default_missing_value("print")
+                .conflicts_with("format"),
+        )
+        .arg(
+            clap::Arg::new("list-fields")
+                .long("list-fields")
+                .help("List the fields of the first line of data, then exit")
+                .long_help(
+                    "List the fields of the first line of data (skipping comments and empty
+lines), split by the delimiter, each preceded by its index like
+\"2: 1896\", to help choosing the index. Nothing else is processed.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["probe", "field-width"]),
+        )
         .arg(
             clap::Arg::new("greater-than")
                 .long("gt")
@@ -76,11 +284,17 @@ default behavior when omitted, unless one of --ge, --lt, or --le
 is specified.
 Gap syntax is according to selected format:
     uint and int: Specified as a signed integer. [default: 1]
-    rfc-3339, unix, and unix_ms: Signed integer followed by one
-        character from [dhms], like \"12h\". [default: 1h]",
+    Timestamp formats and duration: Signed integer followed by one
+        character from [wdhms], like \"12h\", or several of these
+        combined, like \"1h30m\". Longer names \"wk\", \"day\", \"hr\",
+        \"min\" and \"sec\" (also with \"s\" appended) are accepted as
+        well, like \"1hr30min\". [default: 1h]
+Each comparison may also be given by the environment variable of the
+same name (CSVDM_GT, CSVDM_GE, CSVDM_LT or CSVDM_LE), see README.",
                 )
                 .num_args(1)
                 .value_name("GAP")
+                .env("CSVDM_GT")
                 .value_parser(clap::builder::NonEmptyStringValueParser::new())
                 .default_value("1")
                 .hide_default_value(true)
@@ -89,6 +303,7 @@ Gap syntax is according to selected format:
         .arg(
             clap::Arg::new("greater-or-equal")
                 .long("ge")
+                .env("CSVDM_GE")
                 .help("'Greater-or-equal' comparison behavior")
                 .long_help("'Greater-or-equal' comparison behavior, also see -gt.")
                 .num_args(1)
@@ -99,6 +314,7 @@ Gap syntax is according to selected format:
         .arg(
             clap::Arg::new("less-than")
                 .long("lt")
+                .env("CSVDM_LT")
                 .help("'Less-than' comparison behavior")
                 .long_help("'Less-than' comparison behavior, also see -gt.")
                 .num_args(1)
@@ -109,6 +325,7 @@ Gap syntax is according to selected format:
         .arg(
             clap::Arg::new("less-or-equal")
                 .long("le")
+                .env("CSVDM_LE")
                 .help("'Less-or-equal' comparison behavior")
                 .long_help("'Less-or-equal' comparison behavior, also see -gt.")
                 .num_args(1)
@@ -116,6 +333,541 @@ Gap syntax is according to selected format:
                 .value_parser(clap::builder::NonEmptyStringValueParser::new())
                 .conflicts_with_all(["greater-than", "greater-or-equal", "less-than"]),
         )
+        .arg(
+            clap::Arg::new("expect-step")
+                .long("expect-step")
+                .help("Expected difference between values, exactly")
+                .long_help(
+                    "Expected sequence: instead of comparing to a gap, each value is
+expected to be the previous one plus this step, reporting where it
+is not, e.g. skipped IDs with a step of 1. Only valid for integer
+formats. Also see --list-missing.",
+                )
+                .num_args(1)
+                .value_name("STEP")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(i64))
+                .conflicts_with_all([
+                    "greater-than",
+                    "greater-or-equal",
+                    "less-than",
+                    "less-or-equal",
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("expect-start")
+                .long("expect-start")
+                .help("Expected first value of the sequence")
+                .long_help(
+                    "Expected first value of the sequence (and after each --reset-on),
+so that values missing from the start are reported too, as if
+preceded by this value minus the step on line 0.",
+                )
+                .num_args(1)
+                .value_name("START")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(i64))
+                .requires("expect-step"),
+        )
+        .arg(
+            clap::Arg::new("second-diff")
+                .long("second-diff")
+                .help("Compare changes in differences instead")
+                .long_help(
+                    "Second difference: instead of the differences themselves, compare
+how much each one changed since the previous difference, to detect
+drifting cadence (e.g. a degrading sample rate). Nothing is reported
+for the first two values, as there is no previous difference yet.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "expect-step",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("baseline-deviation")
+                .long("baseline-deviation")
+                .help("Report deviations from the first difference, in percent")
+                .long_help(
+                    "Baseline mode: instead of comparing to a gap, the first non-zero
+difference is taken as the natural cadence of the data, and later ones
+are reported if they deviate from it by more than the given percentage
+(in either direction). Zero differences before it are not reported.",
+                )
+                .num_args(1)
+                .value_name("PERCENT")
+                .value_parser(|s: &str| match s.parse::<f64>() {
+                    Ok(p) if p.is_finite() && p >= 0.0 => Ok(p),
+                    _ => Err("must be a non-negative number".to_string()),
+                })
+                .conflicts_with_all([
+                    "greater-than",
+                    "greater-or-equal",
+                    "less-than",
+                    "less-or-equal",
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "expect-step",
+                    "second-diff",
+                    "warn-threshold",
+                    "tally",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("jitter")
+                .long("jitter")
+                .help("Report differences deviating from the mean by SIGMAS")
+                .long_help(
+                    "Jitter mode: instead of comparing to a gap, differences are reported
+if they deviate from the mean of the ones before them by more than
+SIGMAS standard deviations (in either direction), starting from the
+third one. The count, mean and standard deviation of all differences
+are printed to STDERR at the end (in seconds for time based formats).",
+                )
+                .num_args(1)
+                .value_name("SIGMAS")
+                .value_parser(|s: &str| match s.parse::<f64>() {
+                    Ok(p) if p.is_finite() && p >= 0.0 => Ok(p),
+                    _ => Err("must be a non-negative number".to_string()),
+                })
+                .conflicts_with_all([
+                    "greater-than",
+                    "greater-or-equal",
+                    "less-than",
+                    "less-or-equal",
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "expect-step",
+                    "baseline-deviation",
+                    "warn-threshold",
+                    "tally",
+                    "group-by",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("ratio")
+                .long("ratio")
+                .help("Report values changing by more than FACTOR relative to the previous")
+                .long_help(
+                    "Ratio mode: instead of comparing differences to a gap, the ratio of
+each value to the previous one is compared to the given FACTOR, for
+series growing geometrically. A factor of at least 1 reports ratios
+above it (e.g. \"2\" for doubling), a smaller one reports ratios below
+it (e.g. \"0.5\" for halving). A change of sign is always reported, as
+is any change from zero for a factor of at least 1, while zero
+followed by zero is taken as no change. Only valid for integer
+formats.",
+                )
+                .num_args(1)
+                .value_name("FACTOR")
+                .value_parser(|s: &str| match s.parse::<f64>() {
+                    Ok(f) if f.is_finite() && f > 0.0 => Ok(f),
+                    _ => Err("must be a positive number".to_string()),
+                })
+                .conflicts_with_all([
+                    "greater-than",
+                    "greater-or-equal",
+                    "less-than",
+                    "less-or-equal",
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "expect-step",
+                    "baseline-deviation",
+                    "jitter",
+                    "second-diff",
+                    "thresholds",
+                    "warn-threshold",
+                    "tally",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("ema")
+                .long("ema")
+                .help("Report differences deviating from their moving average")
+                .long_help(
+                    "EMA mode: instead of comparing to a gap, differences are compared to
+their exponential moving average, reported if they deviate from it by
+more than --ema-tolerance (in either direction), for cadences that
+drift slowly but are noisy. The average starts at the first non-zero
+difference (not reported itself), then after each comparison moves
+towards the new difference by ALPHA (between 0 and 1) of the way:
+small values like \"0.1\" smooth over many samples and follow changes
+slowly, while \"1\" simply compares to the previous difference. Should
+the average return to 0, it starts over at the next non-zero difference.",
+                )
+                .num_args(1)
+                .value_name("ALPHA")
+                .value_parser(|s: &str| match s.parse::<f64>() {
+                    Ok(a) if a > 0.0 && a <= 1.0 => Ok(a),
+                    _ => Err("must be greater than 0 and at most 1".to_string()),
+                })
+                .conflicts_with_all([
+                    "greater-than",
+                    "greater-or-equal",
+                    "less-than",
+                    "less-or-equal",
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "expect-step",
+                    "baseline-deviation",
+                    "jitter",
+                    "ratio",
+                    "second-diff",
+                    "thresholds",
+                    "warn-threshold",
+                    "tally",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("ema-tolerance")
+                .long("ema-tolerance")
+                .help("Deviation from the moving average tolerated, in percent")
+                .long_help(
+                    "Deviation from the moving average tolerated in EMA mode, in percent
+of the average (in either direction), see --ema.",
+                )
+                .num_args(1)
+                .value_name("PERCENT")
+                .value_parser(|s: &str| match s.parse::<f64>() {
+                    Ok(p) if p.is_finite() && p >= 0.0 => Ok(p),
+                    _ => Err("must be a non-negative number".to_string()),
+                })
+                .default_value("50")
+                .requires("ema"),
+        )
+        .arg(
+            clap::Arg::new("median-window")
+                .long("median-window")
+                .help("Report differences deviating from the median of the last K")
+                .long_help(
+                    "Median mode: instead of comparing to a gap, differences are compared to
+the median of the K differences before them, reported if they deviate
+from it by more than --median-tolerance (in either direction). Unlike
+a mean or the moving average of --ema, the median is not thrown off by
+a few spikes, so it suits noisy cadences. Nothing is reported until
+the first K differences are seen, nor while their median is zero (e.g.
+for repeated timestamps), as there is no cadence to deviate from.",
+                )
+                .num_args(1)
+                .value_name("K")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .conflicts_with_all([
+                    "greater-than",
+                    "greater-or-equal",
+                    "less-than",
+                    "less-or-equal",
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "expect-step",
+                    "baseline-deviation",
+                    "jitter",
+                    "ratio",
+                    "ema",
+                    "second-diff",
+                    "thresholds",
+                    "warn-threshold",
+                    "tally",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("median-tolerance")
+                .long("median-tolerance")
+                .help("Deviation from the moving median tolerated, in percent")
+                .long_help(
+                    "Deviation from the moving median tolerated in median mode, in percent
+of the median (in either direction), see --median-window.",
+                )
+                .num_args(1)
+                .value_name("PERCENT")
+                .value_parser(|s: &str| match s.parse::<f64>() {
+                    Ok(p) if p.is_finite() && p >= 0.0 => Ok(p),
+                    _ => Err("must be a non-negative number".to_string()),
+                })
+                .default_value("50")
+                .requires("median-window"),
+        )
+        .arg(
+            clap::Arg::new("format-drift")
+                .long("format-drift")
+                .help("Report equal subsequent values written differently")
+                .long_help(
+                    "Format drift mode: instead of comparing to a gap, subsequent values are
+reported if they are equal, but their fields are written differently,
+e.g. \"100\" and \"+100\", or the same time in different offsets, as a
+sign of inconsistent formatting upstream. Fields are compared exactly
+as found in the lines, so best seen with --with-lines or --filter.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "greater-than",
+                    "greater-or-equal",
+                    "less-than",
+                    "less-or-equal",
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "expect-step",
+                    "baseline-deviation",
+                    "jitter",
+                    "ratio",
+                    "ema",
+                    "median-window",
+                    "second-diff",
+                    "thresholds",
+                    "warn-threshold",
+                    "tally",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("expr")
+                .long("expr")
+                .help("Report differences satisfying a boolean expression")
+                .long_help(
+                    "Boolean expression over the difference, replacing the comparison to a
+single gap, e.g. \"diff > 1h && diff < 1d\" for gaps within a window.
+Comparisons of \"diff\" and gaps (using the gap syntax of the format,
+see --gt) with >, >=, <, <=, == and != can be combined with &&, ||
+and !, and grouped in parentheses. Validated before reading input.",
+                )
+                .num_args(1)
+                .value_name("EXPR")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all([
+                    "greater-than",
+                    "greater-or-equal",
+                    "less-than",
+                    "less-or-equal",
+                    "stale",
+                    "max-future",
+                    "expect-step",
+                    "baseline-deviation",
+                    "jitter",
+                    "ratio",
+                    "ema",
+                    "median-window",
+                    "format-drift",
+                    "thresholds",
+                    "warn-threshold",
+                    "tally",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("thresholds")
+                .long("thresholds")
+                .help("Gaps varying by value, read from file")
+                .long_help(
+                    "Gaps varying by value (e.g. day and night sampling), read from a file
+with lines of \"FROM,GAP\" in ascending order of FROM values (under the
+format). For each comparison, the gap of the last entry not greater
+than the current value is used, or the main gap before the first one.
+Comments and empty lines are allowed.",
+                )
+                .num_args(1)
+                .value_name("FILE")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all([
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "expect-step",
+                    "warn-threshold",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("warn-threshold")
+                .long("warn-threshold")
+                .help("Less severe gap for two-tier alerting")
+                .long_help(
+                    "Warning threshold for two-tier alerting: differences crossing this
+gap (in the same direction as the comparison) are reported as well,
+and each reported line is tagged with its severity: CRIT when
+crossing the main gap, WARN when only this one, and OK otherwise
+(with --all-diffs or --mark-skipped). Must be less severe than the
+main gap, e.g. smaller for --gt.",
+                )
+                .num_args(1)
+                .value_name("GAP")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all(["filter", "binary-output", "expect-step"]),
+        )
+        .arg(
+            clap::Arg::new("severity-bands")
+                .long("severity-bands")
+                .help("Tag gaps with the band reached by their size, like \"1h=info,6h=crit\"")
+                .long_help(
+                    "Tag each reported gap with a severity band by its size, for dashboards:
+a comma separated list of GAP=LABEL pairs in increasing order of the
+gaps (written as for the format), like \"1h=info,6h=warning,1d=critical\".
+A difference gets the label of the highest gap its magnitude (absolute
+value) reaches, or \"-\" if none. Which lines are reported still depends
+on the comparison, the bands only tag them: in diff, all-diffs and CSV
+modes with an added last field (\"band\" in the header), in JSON mode
+with a \"band\" member (null if none). The generalization of
+--warn-threshold, which cannot be given together with it.",
+                )
+                .num_args(1)
+                .value_name("BANDS")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all(["filter", "binary-output", "warn-threshold"]),
+        )
+        .arg(
+            clap::Arg::new("stale")
+                .long("stale")
+                .help("Stale check: report values older than gap")
+                .long_help(
+                    "Stale check: instead of subsequent lines, each value is compared to
+the current time (taken once at startup), reporting those that are
+older than the given gap. In the output, the current time takes the
+place of the second value. Only valid for timestamp formats.",
+                )
+                .num_args(1)
+                .value_name("GAP")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all([
+                    "greater-than",
+                    "greater-or-equal",
+                    "less-than",
+                    "less-or-equal",
+                    "warn-threshold",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("max-future")
+                .long("max-future")
+                .help("Future check: report values ahead of now by more than gap")
+                .long_help(
+                    "Future check: the counterpart of the stale check, each value is
+compared to the current time (taken once at startup), reporting those
+that are ahead of it by more than the given gap, e.g. due to clock
+skew at the producer. In the output, the current time takes the place
+of the second value, and the difference is how far in the future the
+value is. Only valid for timestamp formats.",
+                )
+                .num_args(1)
+                .value_name("GAP")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all([
+                    "greater-than",
+                    "greater-or-equal",
+                    "less-than",
+                    "less-or-equal",
+                    "warn-threshold",
+                    "stale",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("coverage")
+                .long("coverage")
+                .help("Report coverage at expected CADENCE to STDERR at the end")
+                .long_help(
+                    "Print data completeness to STDERR at the end: the span from the lowest
+to the highest value, the number of values expected in it at the given
+CADENCE (e.g. \"2m\"), the number actually present, and the latter as a
+percentage of the former. Only valid for timestamp formats.",
+                )
+                .num_args(1)
+                .value_name("CADENCE")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all([
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "group-by",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("rate")
+                .long("rate")
+                .help("Report the average rate of values per UNIT to STDERR at the end")
+                .long_help(
+                    "Print the average rate of values to STDERR at the end, e.g. for sizing
+systems by their throughput: the number of values less one, divided
+by the span from the lowest to the highest value (so also for input out
+of order), per the given UNIT of time (e.g. \"1s\" for events per second,
+or \"1m\" per minute), like \"rate: 2.500/60s\". With a span of zero
+(e.g. a single value) there is no rate to tell, which is printed as
+\"rate: NaN\". Only valid for timestamp formats.",
+                )
+                .num_args(1)
+                .value_name("UNIT")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all([
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "group-by",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("compare-to")
+                .long("compare-to")
+                .help("Compare values to those in another file")
+                .long_help(
+                    "Compare values to those in another file, instead of subsequent lines:
+the Nth value of the input is compared to the Nth value of the given
+file (comments and skipped lines are not counted), reporting pairs
+where the difference (other minus input) satisfies the comparison.
+It is an error if one file has more values than the other.",
+                )
+                .num_args(1)
+                .value_name("FILE2")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all(["stale", "max-future"]),
+        )
+        .arg(
+            clap::Arg::new("reference")
+                .long("reference")
+                .help("Report expected values from FILE missing from the input")
+                .long_help(
+                    "Reference check: instead of subsequent lines, reads expected values
+from the given file (one per line, in ascending order, comments and
+empty lines allowed), and reports those for which the closest value
+of the input is farther than the gap (so the gap is the tolerance of
+matching). In the output, the expected value takes the place of the
+first, the closest input value that of the second, and the difference
+is between them. Reported at the end of input, in reference order.",
+                )
+                .num_args(1)
+                .value_name("FILE")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all([
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "group-by",
+                    "thresholds",
+                    "expect-step",
+                    "second-diff",
+                    "baseline-deviation",
+                    "jitter",
+                    "coverage",
+                    "coalesce",
+                    "runs",
+                ]),
+        )
         .arg(
             clap::Arg::new("comment")
                 .short('c')
@@ -129,16 +881,241 @@ string turns off comment detection.",
                 .value_parser(clap::value_parser!(String))
                 .default_value("#"),
         )
+        .arg(
+            clap::Arg::new("reset-on")
+                .long("reset-on")
+                .help("Series separator, starting over after it")
+                .long_help(
+                    "Series separator string: a line starting with it ends the current
+series, so that no gap is reported across it, and the next value starts
+a fresh one. Useful for concatenated files of independent series.",
+                )
+                .num_args(1)
+                .value_name("STR")
+                .allow_hyphen_values(true)
+                .value_parser(clap::builder::NonEmptyStringValueParser::new()),
+        )
         .arg(
             clap::Arg::new("allow-empty")
                 .short('a')
+                .long("allow-empty")
                 .help("Allow empty or invalid lines")
                 .long_help(
                     "Allow empty lines: contrary to default behavior, no error given when
-invalid line is encountered (empty or less fields than expected).",
+invalid line is encountered (empty or less fields than expected).
+Such lines are skipped, comparing the valid lines around them to each
+other, unless --mark-skipped is also given. Same as both
+--allow-empty-lines and --allow-empty-fields.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("allow-empty-lines")
+                .long("allow-empty-lines")
+                .help("Allow empty lines, but not empty fields")
+                .long_help(
+                    "Allow empty lines only, skipping them like -a does, while a line with
+the target field empty or missing is still an error (unless
+--allow-empty-fields is also given).",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("allow-empty-fields")
+                .long("allow-empty-fields")
+                .help("Allow empty or missing fields, but not empty lines")
+                .long_help(
+                    "Allow lines with the target field empty or missing (less fields than
+expected), skipping them like -a does, while an empty line is still
+an error (unless --allow-empty-lines is also given).",
                 )
                 .action(clap::ArgAction::SetTrue),
         )
+        .group(
+            clap::ArgGroup::new("allow")
+                .args(["allow-empty", "allow-empty-lines", "allow-empty-fields"])
+                .multiple(true),
+        )
+        .arg(
+            clap::Arg::new("every")
+                .long("every")
+                .help("Sample every Nth value only")
+                .long_help(
+                    "Sampling for a coarse overview of large inputs: only the first value
+and every Nth after it are compared, ignoring the ones in between
+(comments and skipped lines are not counted). Note that differences
+then span N original intervals, so the gap should be scaled
+accordingly.",
+                )
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .conflicts_with_all(["compare-to", "minus-index"]),
+        )
+        .arg(
+            clap::Arg::new("dedupe-key")
+                .long("dedupe-key")
+                .visible_alias("dedupe-timestamps")
+                .help("Drop values repeating the previous one")
+                .long_help(
+                    "Drop a value equal to the one right before it (as parsed, regardless of
+the rest of the line), keeping only the first of each run of repeats,
+as if the lines of the others were not there. Zero differences are
+then not compared at all, so they are not reported with e.g. --le 0s,
+nor counted in --tally. With --group-by, repeats are dropped within
+each group.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["minus-index", "reference"]),
+        )
+        .arg(
+            clap::Arg::new("max-line-bytes")
+                .long("max-line-bytes")
+                .help("Maximum length of a line in bytes [default: unlimited]")
+                .long_help(
+                    "Maximum length of a line in bytes (without line ending), exceeding
+which is an error. Input is never read further than that, so that
+a corrupted file cannot exhaust memory. Unlimited by default.",
+                )
+                .num_args(1)
+                .value_name("BYTES")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            clap::Arg::new("field-default")
+                .long("field-default")
+                .help("Value to use in place of missing fields")
+                .long_help(
+                    "Default value, used in place of a missing or empty field (or a
+failed extraction) instead of skipping the line or halting. It must be
+valid under the format. Takes precedence over -a (and
+--allow-empty-fields) for such lines, while empty lines are still
+governed by -a (and --allow-empty-lines).",
+                )
+                .num_args(1)
+                .value_name("VALUE")
+                .allow_hyphen_values(true)
+                .value_parser(clap::builder::NonEmptyStringValueParser::new()),
+        )
+        .arg(
+            clap::Arg::new("valid-column")
+                .long("valid-column")
+                .help("Skip lines not marked valid in the field at this index")
+                .long_help(
+                    "Status field marking the validity of each line: lines where the field
+at this index is not equal to the one given by --valid-value (or is
+missing) are skipped, like ones with a missing value with -a.",
+                )
+                .num_args(1)
+                .value_name("INDEX")
+                .value_parser(clap::value_parser!(u16).range(1..))
+                .requires("valid-value"),
+        )
+        .arg(
+            clap::Arg::new("valid-value")
+                .long("valid-value")
+                .help("Status of valid lines, see --valid-column")
+                .num_args(1)
+                .value_name("STR")
+                .allow_hyphen_values(true)
+                .requires("valid-column"),
+        )
+        .arg(
+            clap::Arg::new("json-strings")
+                .long("json-strings")
+                .help("Unescape fields quoted as JSON strings")
+                .long_help(
+                    "Unescape fields quoted as JSON strings (like \"12:00\\u002B01:00\") before
+parsing, instead of only stripping the quotes around them. Escapes are
+different from CSV quoting, e.g. an embedded quote is written as \\\".
+Fields not in quotes are parsed as they are. Note that the delimiter
+is still not allowed inside fields.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("scale")
+                .long("scale")
+                .help("Multiply numeric values by this factor")
+                .long_help(
+                    "Multiply every parsed value by this (non-zero, integer) factor before
+comparison, e.g. to normalize counters incrementing in other units.
+Only for numeric formats. Applied before --offset, and the gap and
+reported values are in the resulting units.",
+                )
+                .num_args(1)
+                .value_name("FACTOR")
+                .allow_negative_numbers(true)
+                .value_parser(|s: &str| match s.parse::<i64>() {
+                    Ok(0) => Err("must not be zero".to_string()),
+                    Ok(f) => Ok(f),
+                    Err(e) => Err(e.to_string()),
+                }),
+        )
+        .arg(
+            clap::Arg::new("offset")
+                .long("offset")
+                .help("Add this offset to values")
+                .long_help(
+                    "Add this integer offset to every parsed value (after --scale), before
+comparison. For time based formats, it is a number of seconds, which
+shifts the reported values only, not the differences.",
+                )
+                .num_args(1)
+                .value_name("N")
+                .allow_negative_numbers(true)
+                .value_parser(clap::value_parser!(i64)),
+        )
+        .arg(
+            clap::Arg::new("wrap-at")
+                .long("wrap-at")
+                .help("Counter wraps to 0 after this maximum")
+                .long_help(
+                    "Maximum of a counter which wraps around to 0 after it (e.g. 65535 for
+a 16-bit one). A negative difference is taken as a wrap, and MAX+1 is
+added to it to recover the true increment before comparison, but only
+if its magnitude exceeds half of MAX, to be conservative. Smaller
+negative differences are compared as they are. Only for numeric
+formats, in the units after --scale.",
+                )
+                .num_args(1)
+                .value_name("MAX")
+                .value_parser(clap::value_parser!(i64).range(1..i64::MAX)),
+        )
+        .arg(
+            clap::Arg::new("wrap-degrees")
+                .long("wrap-degrees")
+                .help("Values are angles wrapping at RANGE (default: 360)")
+                .long_help(
+                    "Values are circular, like compass headings wrapping from 359 to 0
+degrees. Each difference is normalized into [-RANGE/2, RANGE/2), the
+shorter way around, before comparison: from 350 to 10 is a turn of 20,
+and from 10 to 350 one of -20 (negative for turning back). RANGE is
+360 if not given, or e.g. 3600 for tenths of degrees. Only for numeric
+formats, in the units after --scale. Note that the comparison keeps
+the sign, so turns in both directions need e.g. --expr \"diff > 30 ||
+diff < -30\".",
+                )
+                .num_args(0..=1)
+                .require_equals(true)
+                .value_name("RANGE")
+                .value_parser(clap::value_parser!(i64).range(2..))
+                .default_missing_value("360")
+                .conflicts_with("wrap-at"),
+        )
+        .arg(
+            clap::Arg::new("mark-skipped")
+                .long("mark-skipped")
+                .help("Report gap where lines were skipped")
+                .long_help(
+                    "Treat lines skipped due to -a (or either of --allow-empty-lines and
+--allow-empty-fields) as a gap of unknown size: the two valid lines
+around them are always reported, regardless of the comparison.
+Comment lines are not affected.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .requires("allow"),
+        )
         .arg(
             clap::Arg::new("diff")
                 .short('D')
@@ -158,6 +1135,105 @@ the default behavior.",
                 .default_value(",")
                 .default_missing_value(","),
         )
+        .arg(
+            clap::Arg::new("with-lines")
+                .long("with-lines")
+                .help("Append both original lines in diff mode")
+                .long_help(
+                    "Append both original lines to each record in diff mode, after the
+values, separated by the output delimiter as well. Lines containing
+the delimiter or a double quote are quoted like in CSV, with double
+quotes doubled.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "filter",
+                    "csv-output",
+                    "all-diffs",
+                    "binary-output",
+                    "json-output",
+                    "list-missing",
+                    "runs",
+                    "echo-parsed",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("with-difference")
+                .long("with-difference")
+                .help("Append the difference after the values in diff mode")
+                .long_help(
+                    "Append the difference to each record in diff mode, right after the
+values, as in all-diffs mode. Values and differences are written so
+that they can be read again by this program, e.g. to find gaps in
+the gaps: timestamps in rfc-3339 (whatever their input format) and
+durations in the duration format, see README.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "filter",
+                    "csv-output",
+                    "all-diffs",
+                    "binary-output",
+                    "json-output",
+                    "list-missing",
+                    "runs",
+                    "echo-parsed",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("margin")
+                .long("margin")
+                .help("Append how far each gap is from the threshold in diff mode")
+                .long_help(
+                    "Append the margin of each gap in diff mode, for calibrating the
+threshold: the ratio of the difference to the threshold for integer
+formats (e.g. \"1.05x\"), or the difference minus the threshold
+otherwise (e.g. \"3s\"), also for an integer threshold of 0, having no
+ratio. Written after the severity, separated by the output delimiter as
+well.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "filter",
+                    "csv-output",
+                    "all-diffs",
+                    "binary-output",
+                    "json-output",
+                    "list-missing",
+                    "runs",
+                    "echo-parsed",
+                    "output",
+                    "coalesce",
+                    "expect-step",
+                    "baseline-deviation",
+                    "jitter",
+                    "ratio",
+                    "ema",
+                    "median-window",
+                    "format-drift",
+                    "expr",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("position-percent")
+                .long("position-percent")
+                .help("Append the position of each gap in percent through the file")
+                .long_help(
+                    "Append the approximate position of each gap, as the percentage of the
+file read by the time it was found (relative to its length at the
+start), to see whether gaps are clustered or spread out. Written after
+the severity in diff, all-diffs and CSV mode (as column \"position\"),
+and as a \"position\" number in JSON output. Not valid for STDIN.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "filter",
+                    "binary-output",
+                    "list-missing",
+                    "runs",
+                    "echo-parsed",
+                ]),
+        )
         .arg(
             clap::Arg::new("filter")
                 .short('F')
@@ -170,6 +1246,576 @@ lines unchanged, followed by an empty line.",
                 .action(clap::ArgAction::SetTrue)
                 .conflicts_with("diff"),
         )
+        .arg(
+            clap::Arg::new("number-gaps")
+                .long("number-gaps")
+                .help("Number groups in filter mode")
+                .long_help(
+                    "Precede each group of lines in filter mode with a numbered header,
+like \"# gap 3 (lines 142-143)\", instead of separating them by an
+empty line.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .requires("filter"),
+        )
+        .arg(
+            clap::Arg::new("compact")
+                .long("compact")
+                .help("One line per gap in filter mode, joined by SEP")
+                .long_help(
+                    "Write both offending lines of a gap on a single line in filter mode,
+joined by the given separator (\" | \" by default), instead of one
+after the other followed by an empty line.",
+                )
+                .num_args(0..=1)
+                .require_equals(true)
+                .value_name("SEP")
+                .value_parser(clap::value_parser!(String))
+                .default_missing_value(" | ")
+                .requires("filter")
+                .conflicts_with("number-gaps"),
+        )
+        .arg(
+            clap::Arg::new("csv-output")
+                .long("csv-output")
+                .help("CSV mode: header row, then one quoted record per gap")
+                .long_help(
+                    "CSV mode: reports a header row, followed by one comma-separated
+record per gap, containing both lines and both values, plus the
+difference. Fields are quoted according to RFC 4180 when needed.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["diff", "filter"]),
+        )
+        .arg(
+            clap::Arg::new("all-diffs")
+                .long("all-diffs")
+                .help("All-diffs mode: every difference, regardless of gap")
+                .long_help(
+                    "All-diffs mode: reports every pair of subsequent values along with
+their difference, ignoring the comparison altogether. Values are
+separated by the output delimiter of diff mode.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["filter", "csv-output"]),
+        )
+        .arg(
+            clap::Arg::new("binary-output")
+                .long("binary-output")
+                .help("Binary mode: fixed size little-endian records")
+                .long_help(
+                    "Binary mode: reports one fixed size little-endian record per gap,
+containing the line numbers and the difference, for consumption by
+other programs. See README for the exact layout.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["diff", "filter", "csv-output", "all-diffs"]),
+        )
+        .arg(
+            clap::Arg::new("json-output")
+                .long("json-output")
+                .help("JSON mode: one object per gap, on separate lines")
+                .long_help(
+                    "JSON mode: reports one JSON object per gap on separate lines (NDJSON),
+containing the line numbers, lines, values and the difference, the
+latter three as strings.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["diff", "filter", "csv-output", "all-diffs", "binary-output"]),
+        )
+        .arg(
+            clap::Arg::new("json-meta")
+                .long("json-meta")
+                .help("Precede JSON output with run metadata")
+                .long_help(
+                    "Precede JSON output with a metadata object about the run, like
+{\"meta\":{\"version\":..,\"path\":..,\"format\":..,\"comparison\":..,
+\"gap\":..,\"started\":..}}, to help correlating results of many runs.
+Requires JSON output, either by --json-output or --output.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("list-missing")
+                .long("list-missing")
+                .help("Missing mode: list values skipped by the sequence")
+                .long_help(
+                    "Missing mode: instead of the gaps, lists the values missing from the
+expected sequence, one per line. Requires --expect-step.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .requires("expect-step")
+                .conflicts_with_all([
+                    "diff",
+                    "filter",
+                    "csv-output",
+                    "all-diffs",
+                    "binary-output",
+                    "json-output",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("runs")
+                .long("runs")
+                .help("Runs mode: report stretches of values between gaps")
+                .long_help(
+                    "Runs mode: instead of the gaps, reports the runs of values between
+them (ended by a gap, a reset or the end of input), one per line: the
+first and the last value, the number of lines spanned and the
+difference between the two, separated by the output delimiter of diff
+mode.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "filter",
+                    "csv-output",
+                    "all-diffs",
+                    "binary-output",
+                    "json-output",
+                    "list-missing",
+                    "echo-parsed",
+                    "output",
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("coalesce")
+                .long("coalesce")
+                .help("Merge gaps within WINDOW of each other into one")
+                .long_help(
+                    "Merge consecutive gaps into one, as long as each starts within the
+given WINDOW (e.g. \"10m\") from the end of the previous one, to reduce
+noise from flapping data. The merged gap is reported once the next one
+is beyond the window (or at a reset or the end of input), spanning
+from the start of the first gap to the end of the last, with the
+highest severity among them. With --group-by, each group is merged
+separately.",
+                )
+                .num_args(1)
+                .value_name("WINDOW")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all([
+                    "all-diffs",
+                    "runs",
+                    "list-missing",
+                    "echo-parsed",
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("echo-parsed")
+                .long("echo-parsed")
+                .help("Echo mode: line numbers and parsed values only")
+                .long_help(
+                    "Echo mode: for debugging the index and format, reports the line
+number and the parsed value of every valid line, without comparing
+them at all. Values are separated by the output delimiter of diff
+mode.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "filter",
+                    "csv-output",
+                    "all-diffs",
+                    "binary-output",
+                    "json-output",
+                    "list-missing",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("output")
+                .long("output")
+                .help("Write gaps to TARGET in MODE, can be repeated")
+                .long_help(
+                    "Write gaps to the file TARGET (or \"-\" for STDOUT) in the given MODE,
+which is one of \"diff\", \"filter\", \"csv\", \"binary\" or \"json\".
+Can be repeated to write every gap to several outputs at once, e.g.
+\"--output -:diff --output gaps.json:json\". Diff outputs use the
+delimiter of --diff. Replaces the mode options above.",
+                )
+                .num_args(1)
+                .value_name("TARGET:MODE")
+                .allow_hyphen_values(true)
+                .value_parser(|s: &str| match s.rsplit_once(':') {
+                    Some((target, mode))
+                        if !target.is_empty()
+                            && ["diff", "filter", "csv", "binary", "json"].contains(&mode) =>
+                    {
+                        Ok((std::path::PathBuf::from(target), mode.to_string()))
+                    }
+                    _ => Err(
+                        "must be TARGET:MODE, with MODE one of diff, filter, csv, binary, json"
+                            .to_string(),
+                    ),
+                })
+                .action(clap::ArgAction::Append)
+                .conflicts_with_all([
+                    "filter",
+                    "csv-output",
+                    "all-diffs",
+                    "binary-output",
+                    "json-output",
+                    "list-missing",
+                    "echo-parsed",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("lint")
+                .long("lint")
+                .help("Validate only: no output, failing on the first gap")
+                .long_help(
+                    "Lint mode, e.g. for CI: nothing is written to STDOUT, and the first
+gap ends the program with an error naming its line, the lines and
+values around it, and the difference. So it exits with a non-zero
+status if any line is invalid (as always, see -a), or if any
+difference crosses the gap, e.g. --gt \"1h\" for a maximum gap, or
+--lt \"0\" for values never decreasing (--le \"0\" for strictly
+increasing). Zero status means all lines were valid and no gap found.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "filter",
+                    "csv-output",
+                    "all-diffs",
+                    "binary-output",
+                    "json-output",
+                    "list-missing",
+                    "runs",
+                    "echo-parsed",
+                    "output",
+                    "mark-skipped",
+                    "sort-by-gap",
+                    "rank",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("by-day")
+                .long("by-day")
+                .help("Count gaps by calendar day, printed at the end")
+                .long_help(
+                    "Instead of listing the gaps, count them by the calendar day of their
+start (the earlier timestamp), and print the counts at the end, one
+day per line like \"2024-09-30,4\" (using the delimiter of --diff), in
+order of the days. Days without gaps are not listed. The day is taken
+in the offset of the timestamp as written (UTC for Unix timestamps).
+Only valid for timestamp formats.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "filter",
+                    "csv-output",
+                    "all-diffs",
+                    "binary-output",
+                    "json-output",
+                    "list-missing",
+                    "runs",
+                    "echo-parsed",
+                    "output",
+                    "mark-skipped",
+                    "lint",
+                    "sort-by-gap",
+                    "rank",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("peek")
+                .long("peek")
+                .help("Show N lines around the largest gap only")
+                .long_help(
+                    "Instead of listing the gaps, find the one with the largest difference
+and print it with N lines of context before and after, numbered like
+grep does (':' after the two lines of the gap, '-' after the others).
+The input is read a second time for this, so it must be a file.",
+                )
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64))
+                .conflicts_with_all([
+                    "filter",
+                    "csv-output",
+                    "all-diffs",
+                    "binary-output",
+                    "json-output",
+                    "list-missing",
+                    "runs",
+                    "echo-parsed",
+                    "output",
+                    "mark-skipped",
+                    "lint",
+                    "by-day",
+                    "sort-by-gap",
+                    "rank",
+                    "redact",
+                    "seek",
+                    "state",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("redact")
+                .long("redact")
+                .help("Replace values in output with line references")
+                .long_help(
+                    "Replace values (and lines in CSV mode) in the output with a reference
+to their line number, like \"#42\", so that no actual data is
+revealed. In diff mode, the difference is appended to each record.
+Filter mode outputs whole lines, so it cannot be redacted.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("tally")
+                .long("tally")
+                .help("Print tally of differences to STDERR at the end")
+                .long_help(
+                    "Print a summary to STDERR at the end, with the number of differences
+found to be over, equal to, and under the gap (irrespective of the
+comparison used).",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("dump-diffs")
+                .long("dump-diffs")
+                .help("Write every difference to FILE, one per line")
+                .long_help(
+                    "Write every difference compared (the same ones as counted by --tally)
+to FILE, one per line, regardless of the comparison and of the main
+output, e.g. for plotting their distribution. Differences of time based
+formats are written in seconds, with fraction only when needed, and
+without the unit.",
+                )
+                .num_args(1)
+                .value_name("FILE")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new()),
+        )
+        .arg(
+            clap::Arg::new("skip-log")
+                .long("skip-log")
+                .help("Write every line skipped to FILE, with the reason")
+                .long_help(
+                    "Write a record for every line not compared to FILE, like \"12,empty\",
+with the line number and one of the following reasons, regardless of
+the main output:
+    comment: Line starting with the comment prefix.
+    empty: Empty line, allowed by -a or --allow-empty-lines.
+    not-valid: Line not marked valid by --valid-column.
+    missing: Field missing or empty, allowed by -a or
+        --allow-empty-fields (or with --extract, no value extracted).
+    repeat: Value repeating the previous one, with --dedupe-key.",
+                )
+                .num_args(1)
+                .value_name("FILE")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new()),
+        )
+        .arg(
+            clap::Arg::new("prometheus")
+                .long("prometheus")
+                .help("Write gap metrics to FILE in Prometheus text format")
+                .long_help(
+                    "Write metrics to FILE at the end in Prometheus text format, for the
+node_exporter textfile collector: the number of gaps reported, the
+number of differences compared, and the largest gap, labelled with the
+input file name. The file is replaced at once, without partial writes.
+See README for the metric names.",
+                )
+                .num_args(1)
+                .value_name("FILE")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new()),
+        )
+        .arg(
+            clap::Arg::new("round-to")
+                .long("round-to")
+                .help("Round reported differences to the nearest UNIT")
+                .long_help(
+                    "Round the differences in the output to the nearest multiple of UNIT
+(e.g. \"1m\", or \"10\" for integer formats), halves away from zero for
+negative ones as well, for tidier reports. Comparison is still done
+in full precision, and binary output and --dump-diffs are unaffected.",
+                )
+                .num_args(1)
+                .value_name("UNIT")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new()),
+        )
+        .arg(
+            clap::Arg::new("sort-by-gap")
+                .long("sort-by-gap")
+                .help("Sort output by gap magnitude, largest first")
+                .long_help(
+                    "Sort output by the magnitude (absolute value) of the differences,
+largest first, keeping input order for equal ones. Note that all
+reported gaps, including their lines, are kept in memory until the end
+of input, so nothing is written before that.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("rank")
+                .long("rank")
+                .help("Number gaps by magnitude, 1 being the largest")
+                .long_help(
+                    "Precede each gap with its rank by magnitude in diff and CSV output, 1
+being the largest, e.g. for reporting the worst gaps with head(1).
+Implies --sort-by-gap, so nothing is written before the end of input.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "filter",
+                    "all-diffs",
+                    "binary-output",
+                    "json-output",
+                    "list-missing",
+                    "runs",
+                    "echo-parsed",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("annotate")
+                .long("annotate")
+                .help("Precede each gap with the source of its values")
+                .long_help(
+                    "Precede each gap with the source of its values, to tell apart the
+records when they are mixed: the key of the group with --group-by, or
+the column otherwise, as given by --index (e.g. \"3\"), --minus-index
+(e.g. \"-1\") or --from-columns (e.g. \"1+2\"). In diff, all-diffs and
+runs modes it is the first field, followed by the output delimiter; in
+CSV mode it is the first column, \"source\" in the header, after the
+rank if any; in JSON mode it is a \"source\" member, first in the
+object; in missing mode it precedes every value with a comma. Grouped
+output is annotated with the key even without this option, see
+--group-by. Filter, echo and binary outputs cannot be annotated.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["filter", "binary-output", "echo-parsed"]),
+        )
+        .arg(
+            clap::Arg::new("max-buffer-bytes")
+                .long("max-buffer-bytes")
+                .help("Limit of data kept in memory, 0 for none [default: 1 GiB]")
+                .long_help(
+                    "Limit of data kept in memory by features needing more than the current
+lines, like sorting (--sort-by-gap, --rank) or the reference timeline
+(--reference), so that an unexpectedly large input fails early
+instead of exhausting memory. Counted approximately, including the
+lines kept. A limit of 0 turns this off. [default: 1073741824]",
+                )
+                .num_args(1)
+                .value_name("BYTES")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1073741824")
+                .hide_default_value(true),
+        )
+        .arg(
+            clap::Arg::new("line-buffered")
+                .long("line-buffered")
+                .help("Flush output after every line")
+                .long_help(
+                    "Flush output after every line, so that gaps are seen immediately
+even when piped to another program. By default this is only done
+when output is a terminal, and block buffering is used otherwise.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("block-buffered")
+                .long("block-buffered")
+                .help("Flush output only when buffer is full")
+                .long_help(
+                    "Flush output only when buffer is full, even when output is a
+terminal, see --line-buffered.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("line-buffered"),
+        )
+        .arg(
+            clap::Arg::new("wait-for-file")
+                .long("wait-for-file")
+                .help("Wait for input file to appear up to SECONDS")
+                .long_help(
+                    "If the input file does not exist (yet), keep retrying to open it for
+up to SECONDS before giving up, e.g. when started concurrently with
+the program producing it. Other errors, like missing permission, are
+reported immediately. Does not apply to STDIN.",
+                )
+                .num_args(1)
+                .value_name("SECONDS")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            clap::Arg::new("seek")
+                .long("seek")
+                .help("Start reading the input file at byte offset BYTES")
+                .long_help(
+                    "Start reading the input file at the given byte offset, e.g. to resume
+processing a large file where a previous run stopped. If the offset
+falls inside a line, the rest of that line is skipped, so reading
+starts on a line boundary. Line numbers are then counted from there.
+Does not apply to STDIN.",
+                )
+                .num_args(1)
+                .value_name("BYTES")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            clap::Arg::new("state")
+                .long("state")
+                .help("Continue from the end of the previous run, saved to FILE")
+                .long_help(
+                    "For incremental processing of a growing file, e.g. from cron: at the
+end, the byte offset reached and the line of the last value are saved
+to the given file, and at the start, if the file exists, reading
+continues from that offset, comparing the first new value to the
+saved one (as if on line 0, other lines counted from the offset).
+A last line without a newline is left for the next run, as it may
+still be being written. An invalid state file, or an input shorter
+than the offset (e.g. rotated), is ignored with a warning, starting
+from the beginning. Does not apply to STDIN.",
+                )
+                .num_args(1)
+                .value_name("FILE")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .conflicts_with_all([
+                    "seek",
+                    "group-by",
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "reference",
+                ]),
+        )
+        .arg(
+            clap::Arg::new("threads-for-parse")
+                .long("threads-for-parse")
+                .help("Read input on a separate thread from parsing")
+                .long_help(
+                    "Read input on a separate thread, passing it on in blocks to the main
+thread parsing and comparing the lines, so that waiting for slow
+storage (e.g. a network filesystem) overlaps with processing. Input
+is read at most 1 MiB ahead. Parsing remains sequential, so output is
+the same as without this option. See `cargo bench --bench read_ahead`
+for a comparison.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("bar")
+                .long("bar")
+                .help("Show a progress bar on STDERR")
+                .long_help(
+                    "Show a progress bar on STDERR, by the portion of the file read so far
+(relative to its length at the start), erased when reading is done.
+Silently left out when STDERR is not a terminal, or the input is
+STDIN. Best used with the output redirected to a file, so that lines
+written to the terminal do not mix with the bar.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
         .arg(
             clap::Arg::new("verbose")
                 .short('v')
@@ -177,6 +1823,29 @@ lines unchanged, followed by an empty line.",
                 .long_help("Verbose mode: print argument information header (for debug).")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            clap::Arg::new("explain")
+                .long("explain")
+                .help("Explain the first N comparisons on STDERR")
+                .long_help(
+                    "Explain the first N comparisons of subsequent values on STDERR, e.g.
+\"line 5 value X minus line 4 value Y = Z, which is greater than the
+gap T, so reported\", to see why differences are reported or not (sign
+and unit confusion being common causes). Only the first N are
+explained, to avoid flooding.",
+                )
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64))
+                .conflicts_with_all([
+                    "stale",
+                    "max-future",
+                    "compare-to",
+                    "minus-index",
+                    "reference",
+                    "second-diff",
+                ]),
+        )
         .arg(
             clap::Arg::new("FILE")
                 .help("Input file, or '-' to read from STDIN")
@@ -187,14 +1856,219 @@ instead of a file path, input is read from STDIN.",
                 )
                 .value_parser(clap::builder::NonEmptyStringValueParser::new())
                 .required(true),
+        );
+    #[cfg(feature = "encoding")]
+    let command = command.arg(
+        clap::Arg::new("encoding")
+            .long("encoding")
+            .help("Input encoding, e.g. 'latin1' [default: utf-8]")
+            .long_help(
+                "Character encoding of the input, transcoded to UTF-8 before processing.
+Any WHATWG encoding label is accepted, like 'latin1' or 'windows-1252'.
+When omitted, input must be valid UTF-8.",
+            )
+            .num_args(1)
+            .value_name("ENCODING")
+            .value_parser(|s: &str| {
+                encoding_rs::Encoding::for_label(s.as_bytes())
+                    .ok_or(format!("unknown encoding label: '{}'", s))
+            }),
+    );
+    #[cfg(feature = "regex")]
+    let command = command.arg(
+        clap::Arg::new("extract")
+            .long("extract")
+            .help("Extract value by regex capture group")
+            .long_help(
+                "Regular expression with exactly one capture group, used to extract
+the value from the whole line instead of delimiter and index based
+field separation. Lines not matching are treated as invalid.",
+            )
+            .num_args(1)
+            .value_name("REGEX")
+            .conflicts_with_all(["field-width", "minus-index", "exact-fields", "from-columns"])
+            .value_parser(|s: &str| regex::Regex::new(s).map_err(|e| e.to_string())),
+    );
+    #[cfg(feature = "json")]
+    let command = command.arg(
+        clap::Arg::new("json-field")
+            .long("json-field")
+            .help("Select value by dotted path in lines of JSON")
+            .long_help(
+                "Parse each line as a JSON object (e.g. NDJSON logs), and select the
+value at the dotted path (like \"event.timestamp\", or \"readings.0\"
+for the first element of an array) instead of delimiter and index
+based field separation. Strings are unescaped before parsing, numbers
+are taken as written. A missing path or null is treated like an empty
+field (see --allow-empty and --field-default), while lines that are
+not valid JSON, or with an object or array at the path, are invalid.",
+            )
+            .num_args(1)
+            .value_name("PATH")
+            .conflicts_with_all([
+                "delimiter",
+                "delim-any",
+                "auto-delim",
+                "field-width",
+                "index",
+                "minus-index",
+                "from-columns",
+                "exact-fields",
+                "group-by",
+                "valid-column",
+                "list-fields",
+            ])
+            .value_parser(|s: &str| JsonPath::parse(s)),
+    );
+    #[cfg(all(feature = "json", feature = "regex"))]
+    let command = command.mut_arg("json-field", |arg| arg.conflicts_with("extract"));
+    #[cfg(feature = "parallel")]
+    let command = command.arg(
+        clap::Arg::new("jobs")
+            .long("jobs")
+            .help("Number of parallel parsing jobs")
+            .long_help(
+                "Number of jobs parsing the input in parallel. Input is read in large
+blocks, which are split into one chunk per job at line boundaries.
+Comparison is still sequential, so output is the same as without
+this option, only faster for large inputs.",
+            )
+            .num_args(1)
+            .value_name("JOBS")
+            .value_parser(clap::value_parser!(u16).range(1..))
+            .conflicts_with("position-percent"),
+    );
+    #[cfg(all(unix, feature = "socket"))]
+    let command = command
+        .arg(
+            clap::Arg::new("socket")
+                .long("socket")
+                .help("Write JSON output to a Unix socket instead")
+                .long_help(
+                    "Write JSON output (requires --json-output) to a Unix domain socket
+instead of STDOUT, for push-based monitoring. The socket is connected
+before any input is read, and records are sent as soon as found (unless
+--block-buffered). It is closed at the end of input, or on an error,
+while a disconnecting peer stops processing quietly.",
+                )
+                .num_args(1)
+                .value_name("PATH")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .requires("json-output")
+                .conflicts_with("output"),
+        )
+        .arg(
+            clap::Arg::new("socket-listen")
+                .long("socket-listen")
+                .help("Listen on the socket for a single client")
+                .long_help(
+                    "Create the socket and wait for a single client to connect, instead of
+connecting to an existing one. The socket file is removed as soon as
+the client is accepted.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .requires("socket"),
+        );
+    #[cfg(all(unix, feature = "syslog"))]
+    let command = command.arg(
+        clap::Arg::new("syslog")
+            .long("syslog")
+            .help("Send gaps to syslog instead, at SEVERITY (default: warning)")
+            .long_help(
+                "Send each gap to the local syslog daemon as a message at the given
+SEVERITY (warning if not given), instead of writing to STDOUT. The
+message includes the line numbers, the values and the difference. If
+syslog is unavailable at startup, output falls back to STDOUT with a
+warning, while messages failing to be sent later are written to STDERR.",
+            )
+            .num_args(0..=1)
+            .require_equals(true)
+            .value_name("SEVERITY")
+            .value_parser([
+                "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+            ])
+            .default_missing_value("warning")
+            .conflicts_with_all([
+                "filter",
+                "csv-output",
+                "binary-output",
+                "json-output",
+                "list-missing",
+                "runs",
+                "echo-parsed",
+                "output",
+            ]),
+    );
+    #[cfg(all(unix, feature = "socket", feature = "syslog"))]
+    let command = command.mut_arg("syslog", |arg| arg.conflicts_with("socket"));
+    #[cfg(feature = "webhook")]
+    let command = command
+        .arg(
+            clap::Arg::new("webhook")
+                .long("webhook")
+                .help("POST each gap as JSON to URL as well")
+                .long_help(
+                    "Send each gap to URL in a POST request, with the same JSON as in JSON
+output, in addition to the usual output, over HTTP or HTTPS (e.g.
+\"https://alerts.example.com/gaps\"). Connecting, and each write and
+read, times out after 5 seconds. A failed request, or one answered
+with a server error (5xx), is retried twice, after 0.5 and 2 seconds.
+Gaps that still could not be sent (or were refused with any other
+status than 2xx) are written to STDERR after a warning, and the run
+goes on.",
+                )
+                .num_args(1)
+                .value_name("URL")
+                .value_parser(|s: &str| Webhook::parse(s))
+                .conflicts_with_all(["lint", "by-day", "peek"]),
         )
-        .get_matches();
+        .arg(
+            clap::Arg::new("webhook-batch")
+                .long("webhook-batch")
+                .help("Send gaps to the webhook N at a time")
+                .long_help(
+                    "Send gaps to the webhook N at a time in a JSON array, instead of a
+request with a single JSON object for each, to spare the endpoint.
+Any remaining gaps are sent at the end of input, even if fewer.",
+                )
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("1")
+                .requires("webhook"),
+        );
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    let command = command.arg(
+        clap::Arg::new("output-compress")
+            .long("output-compress")
+            .help("Compress output with gzip or zstd")
+            .long_help(
+                "Compress output in the gzip or zstd format, each available if enabled
+by the feature of the same name. Outputs given with --output are
+compressed by their extension anyway if it is \".gz\" or \".zst\", and by
+this option otherwise. Compressed output is always block buffered, and
+cannot be written to a terminal.",
+            )
+            .num_args(1)
+            .value_name("COMPRESSOR")
+            .value_parser([
+                #[cfg(feature = "gzip")]
+                "gzip",
+                #[cfg(feature = "zstd")]
+                "zstd",
+            ]),
+    );
+    #[cfg(all(unix, feature = "socket", any(feature = "gzip", feature = "zstd")))]
+    let command = command.mut_arg("socket", |arg| arg.conflicts_with("output-compress"));
+    let arg_matches = command.get_matches();
 
-    let format: Format = arg_matches
+    let mut formats = arg_matches
         .get_one::<String>("format")
         .unwrap()
-        .to_string()
-        .try_into()?;
+        .split(',')
+        .map(|f| Format::try_from(f.to_string()))
+        .collect::<Result<Vec<Format>, String>>()?;
+    let format = formats.remove(0);
 
     let gt = arg_matches.get_one::<String>("greater-than").cloned();
     let ge = arg_matches.get_one::<String>("greater-or-equal").cloned();
@@ -207,11 +2081,42 @@ instead of a file path, input is read from STDIN.",
         (_, None, None, Some(gap)) => (Comparison::LessOrEqual, gap),
         _ => unreachable!(),
     };
-    let difference = format.parse_diff(gap)?;
 
-    let mode = match arg_matches.get_flag("filter") {
-        true => Mode::Filter,
-        false => Mode::Diff(arg_matches.get_one::<String>("diff").unwrap().to_string()),
+    let odelim = arg_matches.get_one::<String>("diff").unwrap().to_string();
+    let outputs = arg_matches
+        .get_many::<(std::path::PathBuf, String)>("output")
+        .unwrap_or_default()
+        .map(|(path, mode)| {
+            let mode = match mode.as_str() {
+                "diff" => Mode::Diff(odelim.clone()),
+                "filter" => Mode::Filter,
+                "csv" => Mode::Csv,
+                "binary" => Mode::Binary,
+                "json" => Mode::Json,
+                _ => unreachable!(),
+            };
+            (path.clone(), mode)
+        })
+        .collect();
+    let mode = match (
+        arg_matches.get_flag("filter"),
+        arg_matches.get_flag("csv-output"),
+        arg_matches.get_flag("all-diffs"),
+        arg_matches.get_flag("binary-output"),
+        arg_matches.get_flag("json-output"),
+        arg_matches.get_flag("list-missing"),
+        arg_matches.get_flag("runs"),
+        arg_matches.get_flag("echo-parsed"),
+    ) {
+        (true, _, _, _, _, _, _, _) => Mode::Filter,
+        (_, true, _, _, _, _, _, _) => Mode::Csv,
+        (_, _, true, _, _, _, _, _) => Mode::AllDiffs(odelim),
+        (_, _, _, true, _, _, _, _) => Mode::Binary,
+        (_, _, _, _, true, _, _, _) => Mode::Json,
+        (_, _, _, _, _, true, _, _) => Mode::Missing,
+        (_, _, _, _, _, _, true, _) => Mode::Runs(odelim),
+        (_, _, _, _, _, _, _, true) => Mode::Echo(odelim),
+        _ => Mode::Diff(odelim),
     };
 
     let filepath = arg_matches.get_one::<String>("FILE").unwrap();
@@ -219,28 +2124,260 @@ instead of a file path, input is read from STDIN.",
         return Err("Reading from STDIN in interactive mode is not supported".into());
     }
 
-    let args = Arguments {
-        delimiter: arg_matches
-            .get_one::<String>("delimiter")
-            .unwrap()
-            .to_string(),
-        index: *arg_matches.get_one("index").unwrap(),
+    let mut args = Arguments::default();
+    args.delimiter = arg_matches
+        .get_one::<String>("delimiter")
+        .unwrap()
+        .to_string();
+    args.delim_any = arg_matches.get_one::<String>("delim-any").cloned();
+    args.squeeze = arg_matches.get_flag("squeeze");
+    //Explicit delimiter overrides detection, also if given in the environment
+    args.auto_delim = arg_matches.get_flag("auto-delim")
+        && arg_matches.value_source("delimiter") == Some(clap::parser::ValueSource::DefaultValue);
+    args.index = *arg_matches.get_one("index").unwrap();
+    args.minus_index = arg_matches.get_one::<u16>("minus-index").copied();
+    args.from_columns = arg_matches
+        .get_many::<u16>("from-columns")
+        .map_or(Vec::new(), |indices| indices.copied().collect());
+    args.join_with = arg_matches.get_one::<String>("join-with").unwrap().clone();
+    args.exact_fields = arg_matches.get_one::<u16>("exact-fields").copied();
+    args.field_width = arg_matches
+        .get_one::<u16>("field-width")
+        .map(|w| usize::from(*w));
 
-        format,
-        comparison,
-        difference,
+    args.format = format;
+    args.fallback = formats;
+    args.incomparable = match arg_matches
+        .get_one::<String>("incomparable")
+        .map(|s| s.as_str())
+    {
+        Some("flag") => Incomparable::Flag,
+        Some("skip") => Incomparable::Skip,
+        _ => Incomparable::Refuse,
+    };
+    args.comparison = comparison;
+    args.thresholds = arg_matches
+        .get_one::<String>("thresholds")
+        .map(|p| p.into());
+    args.expect_step = arg_matches.get_one::<i64>("expect-step").copied();
+    args.expect_start = arg_matches.get_one::<i64>("expect-start").copied();
+    args.compare_to = arg_matches
+        .get_one::<String>("compare-to")
+        .map(|p| p.into());
+    args.reference = arg_matches.get_one::<String>("reference").map(|p| p.into());
 
-        comment: arg_matches
-            .get_one::<String>("comment")
-            .unwrap()
-            .to_string(),
-        allow_empty: arg_matches.get_flag("allow-empty"),
-        verbose: arg_matches.get_flag("verbose"),
+    args.comment = arg_matches
+        .get_one::<String>("comment")
+        .unwrap()
+        .to_string();
+    args.reset_on = arg_matches.get_one::<String>("reset-on").cloned();
+    args.allow_empty_lines =
+        arg_matches.get_flag("allow-empty") || arg_matches.get_flag("allow-empty-lines");
+    args.allow_empty_fields =
+        arg_matches.get_flag("allow-empty") || arg_matches.get_flag("allow-empty-fields");
+    args.field_default = arg_matches.get_one::<String>("field-default").cloned();
+    args.valid_column = arg_matches.get_one::<u16>("valid-column").copied();
+    args.valid_value = arg_matches
+        .get_one::<String>("valid-value")
+        .cloned()
+        .unwrap_or_default();
+    args.json_strings = arg_matches.get_flag("json-strings");
+    args.scale = arg_matches.get_one::<i64>("scale").copied();
+    args.offset = arg_matches.get_one::<i64>("offset").copied();
+    args.wrap_at = arg_matches.get_one::<i64>("wrap-at").copied();
+    args.wrap_degrees = arg_matches.get_one::<i64>("wrap-degrees").copied();
+    args.group_by = arg_matches.get_one::<u16>("group-by").copied();
+    args.mark_skipped = arg_matches.get_flag("mark-skipped");
+    args.second_diff = arg_matches.get_flag("second-diff");
+    args.baseline_deviation = arg_matches.get_one::<f64>("baseline-deviation").copied();
+    args.jitter = arg_matches.get_one::<f64>("jitter").copied();
+    args.ratio = arg_matches.get_one::<f64>("ratio").copied();
+    args.ema = arg_matches.get_one::<f64>("ema").copied();
+    args.ema_tolerance = *arg_matches.get_one::<f64>("ema-tolerance").unwrap();
+    args.median_window = arg_matches
+        .get_one::<u64>("median-window")
+        .map(|k| *k as usize);
+    args.median_tolerance = *arg_matches.get_one::<f64>("median-tolerance").unwrap();
+    args.format_drift = arg_matches.get_flag("format-drift");
+    args.every = arg_matches.get_one::<u64>("every").copied();
+    args.dedupe_key = arg_matches.get_flag("dedupe-key");
+    args.max_line_bytes = arg_matches
+        .get_one::<u64>("max-line-bytes")
+        .map(|max| usize::try_from(*max).unwrap_or(usize::MAX));
+    args.verbose = arg_matches.get_flag("verbose");
+    args.explain = arg_matches.get_one::<u64>("explain").copied();
+
+    args.mode = mode;
+    args.outputs = outputs;
+    args.lint = arg_matches.get_flag("lint");
+    args.by_day = arg_matches.get_flag("by-day");
+    args.peek = arg_matches.get_one::<u64>("peek").copied();
+    args.number_gaps = arg_matches.get_flag("number-gaps");
+    args.compact = arg_matches.get_one::<String>("compact").cloned();
+    args.with_lines = arg_matches.get_flag("with-lines");
+    args.with_difference = arg_matches.get_flag("with-difference");
+    args.margin = arg_matches.get_flag("margin");
+    args.position_percent = arg_matches.get_flag("position-percent");
+    args.json_meta = arg_matches.get_flag("json-meta");
+    args.redact = arg_matches.get_flag("redact");
+    args.tally = arg_matches.get_flag("tally");
+    args.dump_diffs = arg_matches
+        .get_one::<String>("dump-diffs")
+        .map(|p| p.into());
+    args.skip_log = arg_matches.get_one::<String>("skip-log").map(|p| p.into());
+    args.prometheus = arg_matches
+        .get_one::<String>("prometheus")
+        .map(|p| p.into());
+    args.sort_by_gap = arg_matches.get_flag("sort-by-gap");
+    args.rank = arg_matches.get_flag("rank");
+    args.annotate = arg_matches.get_flag("annotate");
+    args.max_buffer_bytes = *arg_matches.get_one::<u64>("max-buffer-bytes").unwrap();
+    args.buffering = match (
+        arg_matches.get_flag("line-buffered"),
+        arg_matches.get_flag("block-buffered"),
+    ) {
+        (true, _) => Buffering::Line,
+        (_, true) => Buffering::Block,
+        _ => Buffering::Auto,
+    };
+
+    args.wait_for_file = arg_matches.get_one::<u64>("wait-for-file").copied();
+    args.seek = arg_matches.get_one::<u64>("seek").copied();
+    args.state = arg_matches.get_one::<String>("state").map(|p| p.into());
+    args.threads_for_parse = arg_matches.get_flag("threads-for-parse");
+    args.bar = arg_matches.get_flag("bar");
+    args.path = filepath.into();
+    #[cfg(feature = "encoding")]
+    {
+        args.encoding = arg_matches
+            .get_one::<&'static encoding_rs::Encoding>("encoding")
+            .copied();
+    }
+    #[cfg(feature = "regex")]
+    {
+        args.extract = arg_matches.get_one::<regex::Regex>("extract").cloned();
+    }
+    #[cfg(feature = "json")]
+    {
+        args.json_field = arg_matches.get_one::<JsonPath>("json-field").cloned();
+    }
+    #[cfg(feature = "parallel")]
+    {
+        args.jobs = arg_matches.get_one::<u16>("jobs").map(|j| usize::from(*j));
+    }
+    #[cfg(all(unix, feature = "socket"))]
+    {
+        args.socket = arg_matches.get_one::<String>("socket").map(|p| p.into());
+        args.socket_listen = arg_matches.get_flag("socket-listen");
+    }
+    #[cfg(feature = "webhook")]
+    {
+        args.webhook = arg_matches.get_one::<Webhook>("webhook").cloned();
+        args.webhook_batch = *arg_matches.get_one::<u64>("webhook-batch").unwrap() as usize;
+    }
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    {
+        args.compress =
+            arg_matches
+                .get_one::<String>("output-compress")
+                .map(|s| match s.as_str() {
+                    #[cfg(feature = "gzip")]
+                    "gzip" => Compress::Gzip,
+                    #[cfg(feature = "zstd")]
+                    "zstd" => Compress::Zstd,
+                    _ => unreachable!(),
+                });
+    }
+    #[cfg(all(unix, feature = "syslog"))]
+    {
+        args.syslog = arg_matches
+            .get_one::<String>("syslog")
+            .map(|s| match s.as_str() {
+                "emerg" => 0,
+                "alert" => 1,
+                "crit" => 2,
+                "err" => 3,
+                "warning" => 4,
+                "notice" => 5,
+                "info" => 6,
+                _ => 7,
+            });
+    }
 
-        mode,
+    if arg_matches.get_flag("list-fields") {
+        return list_fields(&mut args);
+    }
+    if let Some(probe) = arg_matches.get_one::<String>("probe") {
+        let probed = probe_format(&mut args)?;
+        match probe.as_str() {
+            "use" => args.format = probed,
+            _ => return Ok(()),
+        }
+    }
 
-        path: filepath.into(),
+    let parse_diff = |gap: &String| {
+        args.format
+            .parse_diff(gap.clone())
+            .map_err(|e| e.to_string())
+    };
+    let difference = parse_diff(&gap)?;
+    let warn = match arg_matches.get_one::<String>("warn-threshold") {
+        Some(gap) => Some(parse_diff(gap)?),
+        None => None,
+    };
+    let severity_bands = match arg_matches.get_one::<String>("severity-bands") {
+        Some(bands) => bands
+            .split(',')
+            .map(|band| match band.split_once('=') {
+                Some((gap, label)) if !label.is_empty() => {
+                    Ok((parse_diff(&gap.to_string())?, label.to_string()))
+                }
+                _ => Err(format!(
+                    "invalid severity band '{}': expected GAP=LABEL",
+                    band
+                )),
+            })
+            .collect::<Result<Vec<(Difference, String)>, String>>()?,
+        None => Vec::new(),
+    };
+    let stale = match arg_matches.get_one::<String>("stale") {
+        Some(gap) => Some(parse_diff(gap)?),
+        None => None,
+    };
+    let max_future = match arg_matches.get_one::<String>("max-future") {
+        Some(gap) => Some(parse_diff(gap)?),
+        None => None,
+    };
+    let coalesce = match arg_matches.get_one::<String>("coalesce") {
+        Some(window) => Some(parse_diff(window)?),
+        None => None,
+    };
+    let round_to = match arg_matches.get_one::<String>("round-to") {
+        Some(unit) => Some(parse_diff(unit)?),
+        None => None,
+    };
+    let coverage = match arg_matches.get_one::<String>("coverage") {
+        Some(cadence) => Some(parse_diff(cadence)?),
+        None => None,
+    };
+    let rate = match arg_matches.get_one::<String>("rate") {
+        Some(unit) => Some(parse_diff(unit)?),
+        None => None,
+    };
+    let expr = match arg_matches.get_one::<String>("expr") {
+        Some(expr) => Some(Expr::parse(expr, &args.format)?),
+        None => None,
     };
+    args.difference = difference;
+    args.warn = warn;
+    args.severity_bands = severity_bands;
+    args.stale = stale;
+    args.max_future = max_future;
+    args.coalesce = coalesce;
+    args.round_to = round_to;
+    args.coverage = coverage;
+    args.rate = rate;
+    args.expr = expr;
 
     match csv_detect_missing(args) {
         Err(err) => match err.downcast_ref::<std::io::Error>() {
@@ -0,0 +1,110 @@
+/*
+    Created by Zoltan Kovari, 2024.
+
+    Licensed under the Apache License, Version 2.0
+    http://www.apache.org/licenses/LICENSE-2.0
+    (see LICENSE file)
+*/
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::io::{BufRead, Read};
+
+use rayon::prelude::*;
+
+use super::{
+    check_length, decode_line, parse_line, read_limit, trim_line, Arguments, Handler, Parsed,
+};
+
+//Amount of input parsed by each job at once
+const CHUNK_SIZE: usize = 1 << 20;
+
+type ParsedLine<'a> = std::io::Result<(Cow<'a, str>, Result<Parsed, String>)>;
+
+//Splitting block into roughly equal chunks, at line boundaries
+fn split_chunks(block: &[u8], count: usize) -> Vec<&[u8]> {
+    let size = block.len().div_ceil(count);
+    let mut chunks = Vec::with_capacity(count);
+    let mut rest = block;
+    while !rest.is_empty() {
+        let end = match rest
+            .get(size..)
+            .and_then(|s| s.iter().position(|b| *b == b'\n'))
+        {
+            Some(pos) => size + pos + 1,
+            None => rest.len(),
+        };
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks
+}
+
+fn parse_chunk<'a>(chunk: &'a [u8], args: &Arguments) -> Vec<ParsedLine<'a>> {
+    chunk
+        .split_inclusive(|b| *b == b'\n')
+        .map(|bytes| {
+            check_length(bytes, args)?;
+            let line = match decode_line(bytes, args)? {
+                Cow::Borrowed(s) => Cow::Borrowed(trim_line(s, args)),
+                Cow::Owned(s) => Cow::Owned(trim_line(&s, args).to_string()),
+            };
+            let parsed = parse_line(&line, args);
+            Ok((line, parsed))
+        })
+        .collect()
+}
+
+//Reading input in blocks of whole lines, parsing chunks of each block in
+//parallel, then passing parsed values to the handler in original order, so
+//that comparisons and output are the same as with sequential processing
+pub(crate) fn for_each_line(
+    reader: &mut dyn BufRead,
+    args: &Arguments,
+    jobs: usize,
+    handle: &mut Handler,
+) -> Result<(), Box<dyn Error>> {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+    let mut block: Vec<u8> = Vec::with_capacity(jobs * CHUNK_SIZE);
+    let mut n: u64 = 0;
+
+    loop {
+        block.clear();
+        (&mut *reader)
+            .take((jobs * CHUNK_SIZE) as u64)
+            .read_to_end(&mut block)?;
+        if block.is_empty() {
+            break;
+        }
+        if !block.ends_with(b"\n") {
+            (&mut *reader)
+                .take(read_limit(args))
+                .read_until(b'\n', &mut block)?;
+        }
+        //Possibly still being written, left for the next run to read whole, like sequentially
+        if args.state.is_some() && !block.ends_with(b"\n") {
+            block.truncate(block.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1));
+            if block.is_empty() {
+                break;
+            }
+        }
+
+        let chunks = split_chunks(&block, jobs);
+        let parsed: Vec<Vec<ParsedLine>> = pool.install(|| {
+            chunks
+                .par_iter()
+                .map(|chunk| parse_chunk(chunk, args))
+                .collect()
+        });
+
+        for parsed in parsed.into_iter().flatten() {
+            n += 1;
+            let (line, parsed) = parsed.map_err(|e| format!("line {} {}", n, e))?;
+            let parsed = parsed.map_err(|e| format!("line {} {}", n, e))?;
+            handle(n, &line, parsed)?;
+        }
+    }
+
+    Ok(())
+}
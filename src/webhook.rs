@@ -0,0 +1,142 @@
+/*
+    Created by Zoltan Kovari, 2024.
+
+    Licensed under the Apache License, Version 2.0
+    http://www.apache.org/licenses/LICENSE-2.0
+    (see LICENSE file)
+*/
+
+use std::io::Write;
+use std::time::Duration;
+
+use ureq::http::Uri;
+use ureq::Agent;
+
+//Limit of connecting, and of each write and read, for a single attempt
+const TIMEOUT: Duration = Duration::from_secs(5);
+//Waiting before each retry, so three attempts in all
+const RETRY_DELAYS: [Duration; 2] = [Duration::from_millis(500), Duration::from_secs(2)];
+
+//Endpoint receiving gaps as JSON in POST requests, over HTTP or HTTPS
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+}
+impl Webhook {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let uri: Uri = url
+            .parse()
+            .map_err(|e| format!("invalid URL '{}': {}", url, e))?;
+        if !matches!(uri.scheme_str(), Some("http" | "https")) {
+            return Err(format!(
+                "invalid URL '{}': must start with http:// or https://",
+                url
+            ));
+        }
+        match uri.authority() {
+            Some(authority)
+                if !authority.host().is_empty() && !authority.as_str().contains('@') =>
+            {
+                Ok(Self {
+                    url: url.to_string(),
+                })
+            }
+            _ => Err(format!(
+                "invalid URL '{}': host required, without user",
+                url
+            )),
+        }
+    }
+
+    fn agent() -> Agent {
+        Agent::config_builder()
+            .timeout_connect(Some(TIMEOUT))
+            .timeout_send_request(Some(TIMEOUT))
+            .timeout_send_body(Some(TIMEOUT))
+            .timeout_recv_response(Some(TIMEOUT))
+            .http_status_as_error(false)
+            .user_agent(format!("csv-detect-missing/{}", clap::crate_version!()))
+            .build()
+            .into()
+    }
+
+    //Retrying failures to connect or to get a response, and server errors (5xx), but not
+    //other statuses, as those would likely fail again the same way. Any 2xx status is a success,
+    //the response body is not read.
+    fn post(&self, agent: &Agent, body: &str) -> Result<(), String> {
+        let mut delays = RETRY_DELAYS.iter();
+        loop {
+            let result = agent
+                .post(&self.url)
+                .header("Content-Type", "application/json")
+                .send(body);
+            let err = match result {
+                Ok(response) => match response.status().as_u16() {
+                    200..=299 => return Ok(()),
+                    status @ 500..=599 => {
+                        format!("{} responded with status {}", self.url, status)
+                    }
+                    status => return Err(format!("{} responded with status {}", self.url, status)),
+                },
+                Err(
+                    e @ (ureq::Error::Io(_)
+                    | ureq::Error::Timeout(_)
+                    | ureq::Error::HostNotFound
+                    | ureq::Error::ConnectionFailed),
+                ) => format!("{}: {}", self.url, e),
+                Err(e) => return Err(format!("{}: {}", self.url, e)),
+            };
+            match delays.next() {
+                Some(delay) => std::thread::sleep(*delay),
+                None => return Err(err),
+            }
+        }
+    }
+}
+
+//Gaps waiting to be sent together, as a JSON array when batched, or a single object otherwise
+pub struct Batch<'a> {
+    webhook: &'a Webhook,
+    agent: Agent,
+    size: usize,
+    pending: Vec<String>,
+}
+impl<'a> Batch<'a> {
+    pub fn new(webhook: &'a Webhook, size: usize) -> Self {
+        Self {
+            webhook,
+            agent: Webhook::agent(),
+            size,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, gap: String) -> std::io::Result<()> {
+        self.pending.push(gap);
+        match self.pending.len() >= self.size {
+            true => self.flush(),
+            false => Ok(()),
+        }
+    }
+
+    //Gaps failing to be sent are not lost, but written to STDERR after a warning, like the
+    //messages for syslog
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let body = match self.size {
+            1 => self.pending.join(""),
+            _ => format!("[{}]", self.pending.join(",")),
+        };
+        if let Err(e) = self.webhook.post(&self.agent, &body) {
+            let mut stderr = std::io::stderr();
+            writeln!(stderr, "Warning: could not send to webhook ({}).", e)?;
+            for gap in self.pending.iter() {
+                writeln!(stderr, "{}", gap)?;
+            }
+        }
+        self.pending.clear();
+        Ok(())
+    }
+}
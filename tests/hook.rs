@@ -0,0 +1,44 @@
+/*
+    Created by Zoltan Kovari, 2024.
+
+    Licensed under the Apache License, Version 2.0
+    http://www.apache.org/licenses/LICENSE-2.0
+    (see LICENSE file)
+*/
+
+//Using the detection as a library, like examples/hook.rs, with the gaps passed to a closure
+
+use csv_detect_missing::*;
+
+#[test]
+fn gaps_are_passed_to_closure() {
+    let mut args = Arguments::default();
+    args.index = 2;
+    args.difference = Difference::Number(4);
+    args.comment = "N/A".to_string();
+    let mut input = include_str!("synthetic/winter_olympics.csv").as_bytes();
+
+    let mut gaps = Vec::new();
+    let tally = detect_gaps(args, &mut input, |gap: &Gap| -> Result<(), String> {
+        gaps.push((gap.prev_n(), gap.n(), gap.difference().to_string()));
+        Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(gaps, [(4, Some(7), "12".to_string())]);
+    assert_eq!((tally.over, tally.equal, tally.under), (1, 21, 1));
+}
+
+#[test]
+fn closure_error_stops_detection() {
+    let mut input = "1\n2\n5\n6\n9\n".as_bytes();
+
+    let mut calls = 0;
+    let result = detect_gaps(Arguments::default(), &mut input, |_: &Gap| {
+        calls += 1;
+        Err("queue is full")
+    });
+
+    assert_eq!(result.unwrap_err().to_string(), "queue is full");
+    assert_eq!(calls, 1);
+}